@@ -1,4 +1,4 @@
-use datafrog::Iteration;
+use datafrog::{datalog, Iteration};
 
 #[derive(Copy, Clone, Ord, PartialOrd, Eq, PartialEq, Debug)]
 struct Person {
@@ -21,68 +21,38 @@ struct Tup<'a> {
 }
 
 fn do_match<'a>(triples: &[Tup<'a>]) -> Vec<(Value<'a>, Value<'a>, Value<'a>)> {
-    let mut iteration = Iteration::new();
-
-    let has_name = iteration.variable();
-    let has_parent = iteration.variable();
-
-    // has_name(a, name) <- tuple(a, ":name", name)
-    has_name.extend(
+    // The pairs `(tail, head)` of the tuples described by `desc`.
+    let pairs = |desc: &str| -> Vec<(Value<'a>, Value<'a>)> {
         triples
             .iter()
-            .filter_map(|t| {
-                if t.desc == ":name" {
-                    Some((t.tail, t.head))
-                } else {
-                    None
-                }
-            })
-    );
-
-    // has_parent(a, p) <- tuple(a, ":parent", p)
-    has_parent.extend(
-        triples
-            .iter()
-            .filter_map(|t| {
-                if t.desc == ":parent" {
-                    Some((t.tail, t.head))
-                } else {
-                    None
-                }
-            })
-    );
-
-    let query_1 = iteration.variable();
-    let query_2 = iteration.variable();
-    let query_3 = iteration.variable();
-
-    while iteration.changed() {
-        // query(a, p, name) <- has_name(a, name), has_parent(a, p), has_name(p, name)
-        // aka.
-
-        // query_1(p, [a, a_name]) <- has_name(a, a_name), has_parent(a, p)
-        query_1.from_join(&has_name, &has_parent, |&a, &a_name, &p| (p, (a, a_name)));
-
-        // query_2(a, [p, a_name, p_name]) <- query1(p, [a, a_name]), has_name(p, p_name)
-        query_2.from_join(&query_1, &has_name, |&p, &(a, a_name), &p_name| {
-            (a, (p, a_name, p_name))
-        });
+            .filter(|t| t.desc == desc)
+            .map(|t| (t.tail, t.head))
+            .collect()
+    };
 
-        // query_3(a, p, name) <- query2(a, [p, name, name])
-        query_3.extend(
-            query_2
-                .recent
-                .borrow()
-                .elements
-                .iter()
-                .filter_map(|&(a, (p, a_name, p_name))| {
-                    if a_name == p_name {
-                        Some((a, p, a_name))
-                    } else {
-                        None
-                    }
-                })
-        );
+    let mut iteration = Iteration::new();
+
+    // query(a, p, name) <- has_name(a, name), has_parent(a, p), has_name(p, name)
+    // aka.
+    datalog! {
+        iteration;
+        variables {
+            // has_name(a, name) <- tuple(a, ":name", name)
+            has_name: (Value<'a>, Value<'a>) = pairs(":name"),
+            // has_parent(a, p) <- tuple(a, ":parent", p)
+            has_parent: (Value<'a>, Value<'a>) = pairs(":parent"),
+            query_1: ((Value<'a>, Value<'a>), Value<'a>),
+            query_2: ((Value<'a>, Value<'a>), ()),
+            query_3: (Value<'a>, Value<'a>, Value<'a>),
+        }
+        rules {
+            // query_1([p, name], a) <- has_name(a, name), has_parent(a, p)
+            query_1((p, name), a) <- has_name(a, name), has_parent(a, p);
+            // query_2([p, name]) <- has_name(p, name)
+            query_2((p, name), ()) <- has_name(p, name);
+            // query_3(a, p, name) <- query_1([p, name], a), query_2([p, name])
+            query_3(a, p, name) <- query_1((p, name), a), query_2((p, name), ());
+        }
     }
 
     query_3.complete().elements
@@ -109,15 +79,28 @@ fn main() {
         let mut vec = Vec::with_capacity(100);
 
         for age in 1..100 {
-            vec.push(people.iter().filter(|parent| parent.age >= age + 20 && parent.age <= age + 35).collect::<Vec<_>>());
+            vec.push(
+                people
+                    .iter()
+                    .filter(|parent| parent.age >= age + 20 && parent.age <= age + 35)
+                    .collect::<Vec<_>>(),
+            );
         }
 
         vec
     };
 
     for person in &people {
-        triples.push(Tup { tail: Value::Person(person), desc: ":name", head: Value::String(person.name) });
-        triples.push(Tup { tail: Value::Person(person), desc: ":age", head: Value::Int(person.age) });
+        triples.push(Tup {
+            tail: Value::Person(person),
+            desc: ":name",
+            head: Value::String(person.name),
+        });
+        triples.push(Tup {
+            tail: Value::Person(person),
+            desc: ":age",
+            head: Value::Int(person.age),
+        });
 
         // Find two random parents to assign.
         let possible_parents = &possible_parents_by_age[person.age as usize];
@@ -126,8 +109,16 @@ fn main() {
             let parent_1 = possible_parents[rng.gen_range(0, possible_parents.len() - 1)];
             let parent_2 = possible_parents[rng.gen_range(0, possible_parents.len() - 1)];
 
-            triples.push(Tup { tail: Value::Person(person), desc: ":parent", head: Value::Person(parent_1) });
-            triples.push(Tup { tail: Value::Person(person), desc: ":parent", head: Value::Person(parent_2) });
+            triples.push(Tup {
+                tail: Value::Person(person),
+                desc: ":parent",
+                head: Value::Person(parent_1),
+            });
+            triples.push(Tup {
+                tail: Value::Person(person),
+                desc: ":parent",
+                head: Value::Person(parent_2),
+            });
         }
     }
 
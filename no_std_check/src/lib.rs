@@ -0,0 +1,31 @@
+//! Checks that datafrog, with its default features disabled, builds for
+//! targets without the standard library, such as `thumbv7m-none-eabi`.
+//! The optional features that do not need the standard library are
+//! enabled, so that they are checked as well.
+
+#![no_std]
+
+extern crate alloc;
+
+use alloc::vec::Vec;
+
+use datafrog::{Iteration, Relation};
+
+/// Returns the pairs of nodes `(a, b)` such that `b` is reachable from
+/// `a` in the graph given by `edges`.
+pub fn reachable(edges: Vec<(u32, u32)>) -> Vec<(u32, u32)> {
+    let edges: Relation<(u32, u32)> = edges.into();
+
+    let mut iteration = Iteration::new();
+
+    // reach(B, A) means that B is reachable from A.
+    let reach = iteration.variable::<(u32, u32)>();
+    reach.extend(edges.iter().map(|&(a, b)| (b, a)));
+
+    while iteration.changed() {
+        // reach(C, A) :- reach(B, A), edges(B, C).
+        reach.from_join(&reach, &edges, |_, &a, &c| (c, a));
+    }
+
+    reach.complete().iter().map(|&(b, a)| (a, b)).collect()
+}
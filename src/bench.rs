@@ -0,0 +1,88 @@
+//! Generators for the inputs of common workloads, for use in benchmarks.
+//!
+//! Each generator is deterministic: given the same parameters and seed,
+//! it produces the same relations, so that measurements of different
+//! versions of a computation are made against the same inputs.
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use super::Relation;
+
+/// Generates a graph with `edges` edges between random pairs of the
+/// nodes `0 .. nodes`. Duplicate edges are removed, so the result may
+/// have fewer edges than requested.
+///
+/// # Panics
+///
+/// Panics if `nodes` is zero and `edges` is not.
+pub fn random_graph(nodes: u32, edges: usize, seed: u64) -> Relation<(u32, u32)> {
+    let mut rng = StdRng::seed_from_u64(seed);
+    (0..edges)
+        .map(|_| (rng.gen_range(0, nodes), rng.gen_range(0, nodes)))
+        .collect()
+}
+
+/// Generates a `width` by `height` grid, in which each node has an edge
+/// to its right and lower neighbours. The node in column `x` and row `y`
+/// is numbered `y * width + x`.
+pub fn grid_graph(width: u32, height: u32) -> Relation<(u32, u32)> {
+    let node = |x, y| y * width + x;
+    let mut edges = Vec::new();
+    for y in 0..height {
+        for x in 0..width {
+            if x + 1 < width {
+                edges.push((node(x, y), node(x + 1, y)));
+            }
+            if y + 1 < height {
+                edges.push((node(x, y), node(x, y + 1)));
+            }
+        }
+    }
+    Relation::from_vec(edges)
+}
+
+/// The inputs of the people-matching workload, which looks for people
+/// who share a name with one of their parents.
+pub struct People {
+    /// Pairs each person with their name, one of a handful of names.
+    pub names: Relation<(u32, u32)>,
+    /// Pairs each person with up to two of their parents.
+    pub parents: Relation<(u32, u32)>,
+}
+
+/// Generates `count` people with random names and ages, and assigns each
+/// two random parents that are between 20 and 35 years older, where
+/// there are any.
+pub fn people(count: u32, seed: u64) -> People {
+    const NAMES: u32 = 4;
+
+    let mut rng = StdRng::seed_from_u64(seed);
+    let ages: Vec<u32> = (0..count).map(|_| rng.gen_range(1, 99)).collect();
+
+    let names = (0..count)
+        .map(|person| (person, rng.gen_range(0, NAMES)))
+        .collect();
+
+    let mut people_by_age = vec![Vec::new(); 99];
+    for (person, &age) in ages.iter().enumerate() {
+        people_by_age[age as usize].push(person as u32);
+    }
+
+    let mut parents = Vec::new();
+    for (person, &age) in ages.iter().enumerate() {
+        let oldest = (age as usize + 35).min(98);
+        let candidates: Vec<u32> = people_by_age[(age as usize + 20).min(99)..=oldest].concat();
+        if !candidates.is_empty() {
+            for _ in 0..2 {
+                let parent = candidates[rng.gen_range(0, candidates.len())];
+                parents.push((person as u32, parent));
+            }
+        }
+    }
+
+    People {
+        names,
+        parents: Relation::from_vec(parents),
+    }
+}
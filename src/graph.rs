@@ -0,0 +1,48 @@
+//! Common queries over graphs given as relations of edges.
+
+use super::{join, Iteration, Relation};
+
+/// Returns the nodes of the graph given by `edges` that can reach
+/// themselves, that is, the nodes that lie on a cycle.
+pub fn find_cycles<T: Ord + Clone>(edges: Relation<(T, T)>) -> Relation<T> {
+    reachable(&edges)
+        .elements
+        .into_iter()
+        .filter_map(|(b, a)| if a == b { Some(a) } else { None })
+        .collect()
+}
+
+/// Returns the edges of the graph given by `edges` that are not implied
+/// by a longer path; that is, removes each edge `(a, c)` for which there
+/// is a path from `a` to `c` through some other node `b`.
+///
+/// The graph must be acyclic. On a cycle every edge is implied by the
+/// path around the rest of the cycle, so all of its edges are removed.
+pub fn transitive_reduction<T: Ord + Clone>(mut edges: Relation<(T, T)>) -> Relation<(T, T)> {
+    // implied(A, C) :- reach(B, A), edges(B, C).
+    let implied = Relation::from_join(&reachable(&edges), &edges, |_, a, c| (a.clone(), c.clone()));
+
+    let mut implied = &implied[..];
+    edges.elements.retain(|edge| {
+        implied = join::gallop(implied, |x| x < edge);
+        implied.first() != Some(edge)
+    });
+    edges
+}
+
+/// Returns the pairs `(b, a)` such that `b` is reachable from `a` by a
+/// path of one or more edges.
+fn reachable<T: Ord + Clone>(edges: &Relation<(T, T)>) -> Relation<(T, T)> {
+    let mut iteration = Iteration::new();
+
+    // reach(B, A) means that B is reachable from A.
+    let reach = iteration.variable::<(T, T)>();
+    reach.extend(edges.iter().map(|(a, b)| (b.clone(), a.clone())));
+
+    while iteration.changed() {
+        // reach(C, A) :- reach(B, A), edges(B, C).
+        reach.from_join(&reach, edges, |_, a, c| (c.clone(), a.clone()));
+    }
+
+    reach.complete()
+}
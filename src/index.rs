@@ -0,0 +1,117 @@
+//! Relations with a precomputed index of their keys.
+
+use alloc::collections::BTreeMap;
+use alloc::vec::Vec;
+use core::ops::Range;
+
+use super::treefrog::extend_with_index::ExtendWithIndex;
+use super::Relation;
+
+/// A relation of key-value pairs along with the range of positions
+/// occupied by each key, so that the tuples for a key can be found by a
+/// binary search over the distinct keys rather than over all tuples.
+#[derive(Clone)]
+pub struct IndexedRelation<K: Ord, V: Ord> {
+    relation: Relation<(K, V)>,
+    index: Vec<(K, Range<usize>)>,
+}
+
+impl<K: Ord + Clone, V: Ord> IndexedRelation<K, V> {
+    /// Indexes the keys of `relation`.
+    pub fn new(relation: Relation<(K, V)>) -> Self {
+        let mut index: Vec<(K, Range<usize>)> = Vec::new();
+        for (position, (key, _)) in relation.iter().enumerate() {
+            match index.last_mut() {
+                Some((last, range)) if last == key => range.end = position + 1,
+                _ => index.push((key.clone(), position..position + 1)),
+            }
+        }
+
+        IndexedRelation { relation, index }
+    }
+}
+
+impl<K: Ord, V: Ord> IndexedRelation<K, V> {
+    /// Returns the tuples whose key is `key`, which may be none.
+    pub fn group(&self, key: &K) -> &[(K, V)] {
+        match self.index.binary_search_by(|(k, _)| k.cmp(key)) {
+            Ok(position) => &self.relation[self.index[position].1.clone()],
+            Err(_) => &[],
+        }
+    }
+
+    /// Returns the indexed relation.
+    pub fn relation(&self) -> &Relation<(K, V)> {
+        &self.relation
+    }
+
+    /// Consumes the index, returning the indexed relation.
+    pub fn into_relation(self) -> Relation<(K, V)> {
+        self.relation
+    }
+}
+
+impl<K: Ord + Clone, V: Ord> From<Relation<(K, V)>> for IndexedRelation<K, V> {
+    fn from(relation: Relation<(K, V)>) -> Self {
+        Self::new(relation)
+    }
+}
+
+/// The values of a relation of key-value pairs, grouped by key in a
+/// `BTreeMap`.
+///
+/// Looking up a key takes logarithmic time in the number of keys,
+/// whatever their distribution, where galloping through a sorted
+/// relation can be slow when the keys looked up are sparse. This comes
+/// at the cost of the memory of the map. Use `extend_with` to look up
+/// values in a leapjoin.
+#[derive(Clone)]
+pub struct RelationIndex<K: Ord, V: Ord> {
+    map: BTreeMap<K, Vec<V>>,
+}
+
+impl<K: Ord, V: Ord> RelationIndex<K, V> {
+    /// Returns the values for `key`, in order, which may be none.
+    pub fn get(&self, key: &K) -> &[V] {
+        self.map.get(key).map_or(&[], |values| &values[..])
+    }
+
+    /// Returns the number of distinct keys.
+    pub fn len(&self) -> usize {
+        self.map.len()
+    }
+
+    /// Returns `true` if there are no keys.
+    pub fn is_empty(&self) -> bool {
+        self.map.is_empty()
+    }
+
+    /// Wraps the index as a leaper that extends each source tuple with
+    /// the values for the key selected by `key_func`, like
+    /// `RelationLeaper::extend_with`.
+    pub fn extend_with<'leap, Tuple: Ord, Func: Fn(&Tuple) -> K>(
+        &'leap self,
+        key_func: Func,
+    ) -> ExtendWithIndex<'leap, K, V, Tuple, Func> {
+        ExtendWithIndex::from(self, key_func)
+    }
+}
+
+impl<K: Ord, V: Ord> From<Relation<(K, V)>> for RelationIndex<K, V> {
+    fn from(relation: Relation<(K, V)>) -> Self {
+        let mut map: BTreeMap<K, Vec<V>> = BTreeMap::new();
+        // The values of each key arrive in order, so each list is sorted.
+        for (key, value) in relation.elements {
+            map.entry(key).or_default().push(value);
+        }
+        RelationIndex { map }
+    }
+}
+
+impl<K: Ord + Clone, V: Ord + Clone> Relation<(K, V)> {
+    /// Builds a `RelationIndex` of the relation, for looking up the values
+    /// of sparse keys.
+    pub fn index(&self) -> RelationIndex<K, V> {
+        self.clone().into()
+    }
+}
@@ -1,48 +1,519 @@
 //! Join functionality.
 
-use super::{Relation, Variable};
-use std::cell::Ref;
-use std::ops::Deref;
+use super::{IndexedRelation, Relation, Variable};
+use alloc::vec::Vec;
+use core::cell::Ref;
+use core::cmp::Ordering;
+use core::ops::Deref;
 
 /// Implements `join`. Note that `input1` must be a variable, but
 /// `input2` can be either a variable or a relation. This is necessary
 /// because relations have no "recent" tuples, so the fn would be a
 /// guaranteed no-op if both arguments were relations.  See also
-/// `join_into_relation`.
+/// `join_into_relation`. Results for which `logic` returns `None` are
+/// discarded.
 pub(crate) fn join_into<'me, T1: Ord, T2: Ord, Key: Ord, Result: Ord>(
     input1: &Variable<T1>,
     input2: impl JoinInput<'me, T2>,
     output: &Variable<Result>,
     input1_key: impl Fn(&T1) -> &Key,
     input2_key: impl Fn(&T2) -> &Key,
+    logic: impl FnMut(&Key, &T1, &T2) -> Option<Result>,
+) {
+    output.insert(join_step(input1, input2, input1_key, input2_key, logic));
+}
+
+/// Returns the tuples that `join_into` would insert into its output.
+pub(crate) fn join_step<'me, T1: Ord, T2: Ord, Key: Ord, Result: Ord>(
+    input1: &Variable<T1>,
+    input2: impl JoinInput<'me, T2>,
+    input1_key: impl Fn(&T1) -> &Key,
+    input2_key: impl Fn(&T2) -> &Key,
+    mut logic: impl FnMut(&Key, &T1, &T2) -> Option<Result>,
+) -> Relation<Result> {
+    let mut results = Vec::new();
+
+    for_each_batch_pair(input1, input2, |batch1, batch2| {
+        join_helper(batch1, batch2, &input1_key, &input2_key, |k, v1, v2| {
+            results.extend(logic(k, v1, v2))
+        });
+    });
+
+    Relation::from_vec(results)
+}
+
+/// Like `join_into`, but with a slice of tuples, sorted and distinct,
+/// as the second input. Like a relation, the slice does not change, so
+/// only the recent tuples of `input1` need to be joined with it.
+pub(crate) fn join_into_slice<Key: Ord, V1: Ord, V2: Ord, Result: Ord>(
+    input1: &Variable<(Key, V1)>,
+    input2: &[(Key, V2)],
+    output: &Variable<Result>,
+    mut logic: impl FnMut(&Key, &V1, &V2) -> Result,
+) {
+    let mut results = Vec::new();
+
+    join_helper(
+        &input1.recent.borrow(),
+        input2,
+        |(k, _)| k,
+        |(k, _)| k,
+        |k, (_, v1), (_, v2)| results.push(logic(k, v1, v2)),
+    );
+
+    output.insert(Relation::from_vec(results));
+}
+
+/// The number of results below which `join_into_dedup` does not bother
+/// removing duplicates.
+const DEDUP_THRESHOLD: usize = 1024;
+
+/// Like `join_into`, but removes duplicate results as they accumulate,
+/// whenever their number has doubled since duplicates were last
+/// removed, rather than only once all have been produced.
+pub(crate) fn join_into_dedup<'me, Key: Ord, V1: Ord, V2: Ord, Result: Ord>(
+    input1: &Variable<(Key, V1)>,
+    input2: impl JoinInput<'me, (Key, V2)>,
+    output: &Variable<Result>,
+    mut logic: impl FnMut(&Key, &V1, &V2) -> Result,
+) {
+    let mut results = Vec::new();
+    let mut distinct = 0;
+
+    for_each_batch_pair(input1, input2, |batch1, batch2| {
+        join_helper(
+            batch1,
+            batch2,
+            |(k, _)| k,
+            |(k, _)| k,
+            |k, (_, v1), (_, v2)| {
+                results.push(logic(k, v1, v2));
+                if results.len() >= 2 * distinct.max(DEDUP_THRESHOLD) {
+                    results.sort();
+                    results.dedup();
+                    distinct = results.len();
+                }
+            },
+        );
+    });
+
+    output.insert(Relation::from_vec(results));
+}
+
+/// Invokes `f` for each result that `join_into` would produce from the
+/// same inputs in this round, without collecting them.
+pub(crate) fn for_each_join<'me, Key: Ord, V1: Ord, V2: Ord>(
+    input1: &Variable<(Key, V1)>,
+    input2: impl JoinInput<'me, (Key, V2)>,
+    mut f: impl FnMut(&Key, &V1, &V2),
+) {
+    for_each_batch_pair(input1, input2, |batch1, batch2| {
+        join_helper(
+            batch1,
+            batch2,
+            |(k, _)| k,
+            |(k, _)| k,
+            |k, (_, v1), (_, v2)| f(k, v1, v2),
+        );
+    });
+}
+
+/// Like `join_into`, but for keys that need not be ordered consistently
+/// with the tuples. Each pair of batches is sorted by key before being
+/// joined.
+pub(crate) fn join_into_by<'me, T1: Ord, T2: Ord, Key: Ord, Result: Ord>(
+    input1: &Variable<T1>,
+    input2: impl JoinInput<'me, T2>,
+    output: &Variable<Result>,
+    input1_key: impl Fn(&T1) -> &Key,
+    input2_key: impl Fn(&T2) -> &Key,
+    mut logic: impl FnMut(&Key, &T1, &T2) -> Result,
+) {
+    let mut results = Vec::new();
+
+    for_each_batch_pair(input1, input2, |batch1, batch2| {
+        let mut sorted1: Vec<&T1> = batch1.iter().collect();
+        let mut sorted2: Vec<&T2> = batch2.iter().collect();
+        sorted1.sort_by(|x, y| input1_key(x).cmp(input1_key(y)));
+        sorted2.sort_by(|x, y| input2_key(x).cmp(input2_key(y)));
+
+        join_helper(
+            &sorted1,
+            &sorted2,
+            |x| input1_key(x),
+            |y| input2_key(y),
+            |k, v1, v2| results.push(logic(k, v1, v2)),
+        );
+    });
+
+    output.insert(Relation::from_vec(results));
+}
+
+/// Like `join_into_by`, but for keys computed from the tuples rather
+/// than borrowed from them. The key of each tuple is computed once, and
+/// kept alongside it while the batches are sorted and joined.
+pub(crate) fn join_into_by_key<'me, T1: Ord, T2: Ord, Key: Ord, Result: Ord>(
+    input1: &Variable<T1>,
+    input2: impl JoinInput<'me, T2>,
+    output: &Variable<Result>,
+    input1_key: impl Fn(&T1) -> Key,
+    input2_key: impl Fn(&T2) -> Key,
     mut logic: impl FnMut(&Key, &T1, &T2) -> Result,
 ) {
     let mut results = Vec::new();
 
+    for_each_batch_pair(input1, input2, |batch1, batch2| {
+        let mut keyed1: Vec<(Key, &T1)> = batch1.iter().map(|x| (input1_key(x), x)).collect();
+        let mut keyed2: Vec<(Key, &T2)> = batch2.iter().map(|y| (input2_key(y), y)).collect();
+        keyed1.sort_by(|x, y| x.0.cmp(&y.0));
+        keyed2.sort_by(|x, y| x.0.cmp(&y.0));
+
+        join_helper(
+            &keyed1,
+            &keyed2,
+            |(k, _)| k,
+            |(k, _)| k,
+            |k, (_, v1), (_, v2)| results.push(logic(k, v1, v2)),
+        );
+    });
+
+    output.insert(Relation::from_vec(results));
+}
+
+/// Like `join_into`, but produces two results from each pair of
+/// matching tuples, one for each of two outputs.
+pub(crate) fn join_into_rekeyed<'me, Key: Ord, V1: Ord, V2: Ord, R1: Ord, R2: Ord>(
+    input1: &Variable<(Key, V1)>,
+    input2: impl JoinInput<'me, (Key, V2)>,
+    output1: &Variable<R1>,
+    output2: &Variable<R2>,
+    mut logic1: impl FnMut(&Key, &V1, &V2) -> R1,
+    mut logic2: impl FnMut(&Key, &V1, &V2) -> R2,
+) {
+    let mut results1 = Vec::new();
+    let mut results2 = Vec::new();
+
+    for_each_batch_pair(input1, input2, |batch1, batch2| {
+        join_helper(
+            batch1,
+            batch2,
+            |(k, _)| k,
+            |(k, _)| k,
+            |k, (_, v1), (_, v2)| {
+                results1.push(logic1(k, v1, v2));
+                results2.push(logic2(k, v1, v2));
+            },
+        );
+    });
+
+    output1.insert(Relation::from_vec(results1));
+    output2.insert(Relation::from_vec(results2));
+}
+
+/// Like `join_into`, but produces a single result for each key that
+/// matches, from the first pair of values with that key, rather than
+/// one for each pair.
+///
+/// A key produces its result in the round in which it first matches,
+/// from one pair of batches, and is skipped by all other pairs in that
+/// round; a key present in a stable batch of each input has matched in
+/// an earlier round.
+pub(crate) fn join_into_exists<'me, Key: Ord, V1: Ord, V2: Ord, Result: Ord>(
+    input1: &Variable<(Key, V1)>,
+    input2: impl JoinInput<'me, (Key, V2)>,
+    output: &Variable<Result>,
+    mut logic: impl FnMut(&Key, &V1, &V2) -> Result,
+) {
+    let mut results = Vec::new();
+
     let recent1 = input1.recent();
+    let stable1 = input1.stable();
     let recent2 = input2.recent();
+    let stable2 = input2.stable();
+    let in_stable1 = |key: &Key| stable1.iter().any(|batch| has_key(batch, key));
+    let in_stable2 = |key: &Key| stable2.iter().any(|batch| has_key(batch, key));
+
+    // Each pair of batches skips the keys that another pair joins, and
+    // those that matched in an earlier round. Both groups are non-empty;
+    // skip the rest of the cross-product.
+    for (index, batch2) in stable2.iter().enumerate() {
+        join_groups(
+            &recent1,
+            batch2,
+            |(k, _)| k,
+            |(k, _)| k,
+            |key, group1, group2| {
+                // Keys in the recent batch of `input2` are joined below, and
+                // keys in an earlier stable batch with this one.
+                if !in_stable1(key)
+                    && !has_key(&recent2, key)
+                    && !stable2[..index].iter().any(|batch| has_key(batch, key))
+                {
+                    results.push(logic(key, &group1[0].1, &group2[0].1));
+                }
+            },
+        );
+    }
 
-    {
-        // scoped to let `closure` drop borrow of `results`.
+    for (index, batch1) in stable1.iter().enumerate() {
+        join_groups(
+            batch1,
+            &recent2,
+            |(k, _)| k,
+            |(k, _)| k,
+            |key, group1, group2| {
+                if !in_stable2(key)
+                    && !has_key(&recent1, key)
+                    && !stable1[..index].iter().any(|batch| has_key(batch, key))
+                {
+                    results.push(logic(key, &group1[0].1, &group2[0].1));
+                }
+            },
+        );
+    }
+
+    join_groups(
+        &recent1,
+        &recent2,
+        |(k, _)| k,
+        |(k, _)| k,
+        |key, group1, group2| {
+            if !(in_stable1(key) && in_stable2(key)) {
+                results.push(logic(key, &group1[0].1, &group2[0].1));
+            }
+        },
+    );
+
+    output.insert(Relation::from_vec(results));
+}
+
+/// Panics if a key of `recent` appears in it more than once, or in any
+/// of the `stable` batches.
+fn assert_unique_keys<Key: Ord, Val: Ord>(recent: &[(Key, Val)], stable: &[Relation<(Key, Val)>]) {
+    for (index, (key, _)) in recent.iter().enumerate() {
+        let repeated = recent.get(index + 1).map(|(next, _)| next) == Some(key);
+        assert!(
+            !repeated && !stable.iter().any(|batch| has_key(batch, key)),
+            "key is not unique"
+        );
+    }
+}
+
+/// Returns whether some tuple of `batch`, which is sorted, has `key`.
+fn has_key<Key: Ord, Val>(batch: &[(Key, Val)], key: &Key) -> bool {
+    batch.binary_search_by(|(k, _)| k.cmp(key)).is_ok()
+}
+
+/// Like `join_into`, but joins three inputs on a common key.
+///
+/// Each input is made up of stable batches and one recent batch, and
+/// the join is run on each combination of batches that includes at
+/// least one recent batch, as all others have been joined before.
+pub(crate) fn join_into3<'me, Key: Ord, V1: Ord, V2: Ord, V3: Ord, Result: Ord>(
+    input1: &Variable<(Key, V1)>,
+    input2: impl JoinInput<'me, (Key, V2)>,
+    input3: impl JoinInput<'me, (Key, V3)>,
+    output: &Variable<Result>,
+    mut logic: impl FnMut(&Key, &V1, &V2, &V3) -> Result,
+) {
+    let mut results = Vec::new();
 
-        let mut closure = |k: &Key, v1: &T1, v2: &T2| results.push(logic(k, v1, v2));
+    let (recent1, stable1) = (input1.recent(), input1.stable());
+    let (recent2, stable2) = (input2.recent(), input2.stable());
+    let (recent3, stable3) = (input3.recent(), input3.stable());
 
-        for batch2 in input2.stable().iter() {
-            join_helper(&recent1, &batch2, &input1_key, &input2_key, &mut closure);
+    // Each batch, paired with whether it is the recent one.
+    let batches1: Vec<(&[(Key, V1)], bool)> = stable1
+        .iter()
+        .map(|batch| (&batch[..], false))
+        .chain(Some((&recent1[..], true)))
+        .collect();
+    let batches2: Vec<(&[(Key, V2)], bool)> = stable2
+        .iter()
+        .map(|batch| (&batch[..], false))
+        .chain(Some((&recent2[..], true)))
+        .collect();
+    let batches3: Vec<(&[(Key, V3)], bool)> = stable3
+        .iter()
+        .map(|batch| (&batch[..], false))
+        .chain(Some((&recent3[..], true)))
+        .collect();
+
+    for &(batch1, is_recent1) in batches1.iter() {
+        for &(batch2, is_recent2) in batches2.iter() {
+            for &(batch3, is_recent3) in batches3.iter() {
+                if is_recent1 || is_recent2 || is_recent3 {
+                    join_helper3(batch1, batch2, batch3, |k, v1, v2, v3| {
+                        results.push(logic(k, v1, v2, v3))
+                    });
+                }
+            }
+        }
+    }
+
+    output.insert(Relation::from_vec(results));
+}
+
+/// Like `join_into`, but for inputs that have at most one tuple per
+/// key, which is checked in debug builds.
+pub(crate) fn join_into_one_to_one<'me, Key: Ord, V1: Ord, V2: Ord, Result: Ord>(
+    input1: &Variable<(Key, V1)>,
+    input2: impl JoinInput<'me, (Key, V2)>,
+    output: &Variable<Result>,
+    mut logic: impl FnMut(&Key, &V1, &V2) -> Result,
+) {
+    let mut results = Vec::new();
+
+    // Batches are only joined in pairs, so keys repeated in another batch
+    // of the same input would go unnoticed by the check below.
+    if cfg!(debug_assertions) {
+        assert_unique_keys(&input1.recent(), &input1.stable());
+        assert_unique_keys(&input2.recent(), &input2.stable());
+    }
+
+    for_each_batch_pair(input1, input2, |batch1, batch2| {
+        join_groups(
+            batch1,
+            batch2,
+            |(k, _)| k,
+            |(k, _)| k,
+            |k, group1, group2| {
+                debug_assert!(group1.len() == 1 && group2.len() == 1, "key is not unique");
+                results.push(logic(k, &group1[0].1, &group2[0].1));
+            },
+        );
+    });
+
+    output.insert(Relation::from_vec(results));
+}
+
+/// Like `join_into`, but for an `IndexedRelation` as the second input.
+/// As it has no recent tuples, only the recent tuples of `input1` can
+/// produce new results.
+pub(crate) fn join_into_indexed<Key: Ord, V1: Ord, V2: Ord, Result: Ord>(
+    input1: &Variable<(Key, V1)>,
+    input2: &IndexedRelation<Key, V2>,
+    output: &Variable<Result>,
+    mut logic: impl FnMut(&Key, &V1, &V2) -> Result,
+) {
+    let mut results = Vec::new();
+
+    for (key, v1) in input1.recent.borrow().iter() {
+        for (_, v2) in input2.group(key) {
+            results.push(logic(key, v1, v2));
         }
+    }
+
+    output.insert(Relation::from_vec(results));
+}
+
+/// Joins `variable` with itself, matching the target of each edge with
+/// the source of another, and adds the results back into `variable`.
+/// The tuples must be ordered by their source first.
+pub(crate) fn join_into_transitive<Key: Ord, Tuple: Ord>(
+    variable: &Variable<Tuple>,
+    edge_key: impl Fn(&Tuple) -> (&Key, &Key),
+    mut logic: impl FnMut(&Tuple, &Tuple) -> Tuple,
+) {
+    let mut results = Vec::new();
+
+    // Both sides borrow the same `recent` and `stable` cells, but only
+    // immutably; the results are held back until the borrows end.
+    for_each_batch_pair(variable, variable, |batch1, batch2| {
+        // Each batch is sorted by source, so the left-hand side must be
+        // re-sorted by target to be joined.
+        let mut by_target: Vec<&Tuple> = batch1.iter().collect();
+        by_target.sort_by(|x, y| edge_key(x).1.cmp(edge_key(y).1));
+
+        join_helper(
+            &by_target,
+            batch2,
+            |x| edge_key(x).1,
+            |y| edge_key(y).0,
+            |_, x, y| results.push(logic(x, y)),
+        );
+    });
+
+    variable.insert(Relation::from_vec(results));
+}
+
+/// Adds the result of `logic` for each pair of tuples from `input1` and
+/// `input2` that has not been combined before.
+pub(crate) fn product_into<'me, T1: Ord, T2: Ord, Result: Ord>(
+    input1: &Variable<T1>,
+    input2: impl JoinInput<'me, T2>,
+    output: &Variable<Result>,
+    mut logic: impl FnMut(&T1, &T2) -> Result,
+) {
+    let mut results = Vec::new();
 
-        for batch1 in input1.stable().iter() {
-            join_helper(&batch1, &recent2, &input1_key, &input2_key, &mut closure);
+    for_each_batch_pair(input1, input2, |batch1, batch2| {
+        for tuple1 in batch1 {
+            for tuple2 in batch2 {
+                results.push(logic(tuple1, tuple2));
+            }
         }
+    });
+
+    output.insert(Relation::from_vec(results));
+}
+
+/// Invokes `f` with each pair of batches from `input1` and `input2`
+/// whose join may produce new tuples: that is, those pairs in which at
+/// least one batch is recent.
+fn for_each_batch_pair<'me, T1: Ord, T2: Ord>(
+    input1: &Variable<T1>,
+    input2: impl JoinInput<'me, T2>,
+    mut f: impl FnMut(&[T1], &[T2]),
+) {
+    let recent1 = input1.recent();
+    let recent2 = input2.recent();
 
-        join_helper(&recent1, &recent2, input1_key, input2_key, &mut closure);
+    for batch2 in input2.stable().iter() {
+        f(&recent1, batch2);
     }
 
+    for batch1 in input1.stable().iter() {
+        f(batch1, &recent2);
+    }
+
+    f(&recent1, &recent2);
+}
+
+/// Like `join_into`, but the keys of each input are converted into a
+/// common key type before being compared.
+pub(crate) fn join_into_converted<
+    'me,
+    K1: Ord,
+    V1: Ord,
+    K2: Ord,
+    V2: Ord,
+    Key: Ord,
+    Result: Ord,
+>(
+    input1: &Variable<(K1, V1)>,
+    input2: impl JoinInput<'me, (K2, V2)>,
+    output: &Variable<Result>,
+    to_common1: impl Fn(&K1) -> Key,
+    to_common2: impl Fn(&K2) -> Key,
+    mut logic: impl FnMut(&Key, &V1, &V2) -> Result,
+) {
+    let mut results = Vec::new();
+
+    let key1 = |(k1, _): &(K1, V1)| to_common1(k1);
+    let key2 = |(k2, _): &(K2, V2)| to_common2(k2);
+
+    for_each_batch_pair(input1, input2, |batch1, batch2| {
+        join_helper_converted(batch1, batch2, key1, key2, |k, (_, v1), (_, v2)| {
+            results.push(logic(k, v1, v2))
+        });
+    });
+
     output.insert(Relation::from_vec(results));
 }
 
 /// Join, but for two relations.
-pub(crate) fn join_into_relation<'me, Key: Ord, T1: Ord, T2: Ord, Result: Ord>(
+pub(crate) fn join_into_relation<Key: Ord, T1: Ord, T2: Ord, Result: Ord>(
     input1: &Relation<T1>,
     input2: &Relation<T2>,
     input1_key: impl Fn(&T1) -> &Key,
@@ -51,27 +522,54 @@ pub(crate) fn join_into_relation<'me, Key: Ord, T1: Ord, T2: Ord, Result: Ord>(
 ) -> Relation<Result> {
     let mut results = Vec::new();
 
-    join_helper(&input1.elements, &input2.elements, input1_key, input2_key, |k, v1, v2| {
-        results.push(logic(k, v1, v2));
-    });
+    join_helper(
+        &input1.elements,
+        &input2.elements,
+        input1_key,
+        input2_key,
+        |k, v1, v2| {
+            results.push(logic(k, v1, v2));
+        },
+    );
+
+    Relation::from_vec(results)
+}
+
+/// Transforms the tuples of `input1` whose keys are not present in
+/// `input2`; for a variable, `input1` holds its recent tuples.
+pub(crate) fn antijoin<Key: Ord, Val: Ord, Result: Ord>(
+    input1: &[(Key, Val)],
+    input2: &[Key],
+    mut logic: impl FnMut(&Key, &Val) -> Result,
+) -> Relation<Result> {
+    let mut tuples2 = input2;
+
+    let results = input1
+        .iter()
+        .filter(|(ref key, _)| {
+            tuples2 = gallop(tuples2, |k| k < key);
+            tuples2.first() != Some(key)
+        })
+        .map(|(ref key, ref val)| logic(key, val))
+        .collect::<Vec<_>>();
 
     Relation::from_vec(results)
 }
 
-/// Moves all recent tuples from `input1` that are not present in `input2` into `output`.
-pub(crate) fn antijoin<'me, Key: Ord, Val: Ord, Result: Ord>(
+/// Moves all recent tuples from `input1` that are present in `input2` into `output`.
+pub(crate) fn semijoin<'me, Key: Ord, Val: Ord, Result: Ord>(
     input1: impl JoinInput<'me, (Key, Val)>,
-    input2: &Relation<Key>,
+    input2: &[Key],
     mut logic: impl FnMut(&Key, &Val) -> Result,
 ) -> Relation<Result> {
-    let mut tuples2 = &input2[..];
+    let mut tuples2 = input2;
 
     let results = input1
         .recent()
         .iter()
         .filter(|(ref key, _)| {
             tuples2 = gallop(tuples2, |k| k < key);
-            tuples2.first() != Some(key)
+            tuples2.first() == Some(key)
         })
         .map(|(ref key, ref val)| logic(key, val))
         .collect::<Vec<_>>();
@@ -79,68 +577,247 @@ pub(crate) fn antijoin<'me, Key: Ord, Val: Ord, Result: Ord>(
     Relation::from_vec(results)
 }
 
+/// Moves the recent tuples of `input` whose key, as computed by
+/// `input_key`, is not present in `relation` into `output`.
+pub(crate) fn filter_anti_into<Key: Ord, Tuple: Ord + Clone>(
+    input: &Variable<Tuple>,
+    relation: &Relation<Key>,
+    output: &Variable<Tuple>,
+    input_key: impl Fn(&Tuple) -> Key,
+) {
+    let mut keys = &relation[..];
+    let mut previous: Option<Key> = None;
+
+    let results = input
+        .recent
+        .borrow()
+        .iter()
+        .filter(|tuple| {
+            let key = input_key(tuple);
+            // Keys need not be ordered consistently with the tuples; one
+            // that goes backwards searches again from the start.
+            if previous.as_ref() > Some(&key) {
+                keys = &relation[..];
+            }
+            keys = gallop(keys, |k| k < &key);
+            let absent = keys.first() != Some(&key);
+            previous = Some(key);
+            absent
+        })
+        .cloned()
+        .collect::<Vec<_>>();
+
+    // The tuples come from a relation, and remain sorted and distinct.
+    output.insert(Relation { elements: results });
+}
+
+/// Like `antijoin`, but with the key of each tuple selected by
+/// `input1_key`. As the selected keys need not be ordered consistently
+/// with the tuples, each is looked up in `input2` by binary search.
+pub(crate) fn antijoin_by<Key: Ord, T1: Ord, Result: Ord>(
+    input1: &Variable<T1>,
+    input2: &Relation<Key>,
+    input1_key: impl Fn(&T1) -> &Key,
+    logic: impl FnMut(&T1) -> Result,
+) -> Relation<Result> {
+    let results = input1
+        .recent
+        .borrow()
+        .iter()
+        .filter(|tuple| input2.binary_search(input1_key(tuple)).is_err())
+        .map(logic)
+        .collect::<Vec<_>>();
+
+    Relation::from_vec(results)
+}
+
 fn join_helper<K: Ord, T1, T2>(
-    mut slice1: &[T1],
-    mut slice2: &[T2],
+    slice1: &[T1],
+    slice2: &[T2],
     slice1_key: impl Fn(&T1) -> &K,
     slice2_key: impl Fn(&T2) -> &K,
     mut result: impl FnMut(&K, &T1, &T2),
 ) {
-    while !slice1.is_empty() && !slice2.is_empty() {
-        use std::cmp::Ordering;
+    join_groups(
+        slice1,
+        slice2,
+        slice1_key,
+        slice2_key,
+        |key, group1, group2| {
+            // Produce results from the cross-product of matches.
+            for s1 in group1 {
+                for s2 in group2 {
+                    result(key, s1, s2);
+                }
+            }
+        },
+    )
+}
 
+/// Like `join_helper`, but for three slices of `(key, value)` pairs.
+fn join_helper3<K: Ord, V1, V2, V3>(
+    slice1: &[(K, V1)],
+    slice2: &[(K, V2)],
+    mut slice3: &[(K, V3)],
+    mut result: impl FnMut(&K, &V1, &V2, &V3),
+) {
+    join_groups(
+        slice1,
+        slice2,
+        |(k, _)| k,
+        |(k, _)| k,
+        |key, group1, group2| {
+            // The keys are increasing, so the third slice only moves forward.
+            slice3 = gallop(slice3, |(k, _)| k < key);
+            let count3 = slice3.iter().take_while(|(k, _)| k == key).count();
+
+            for (_, v1) in group1 {
+                for (_, v2) in group2 {
+                    for (_, v3) in slice3[..count3].iter() {
+                        result(key, v1, v2, v3);
+                    }
+                }
+            }
+        },
+    )
+}
+
+/// Invokes `result` once for each key present in both slices, with the
+/// runs of tuples in each slice that share that key.
+pub(crate) fn join_groups<K: Ord, T1, T2>(
+    slice1: &[T1],
+    slice2: &[T2],
+    slice1_key: impl Fn(&T1) -> &K,
+    slice2_key: impl Fn(&T2) -> &K,
+    mut result: impl FnMut(&K, &[T1], &[T2]),
+) {
+    join_groups_by(
+        slice1,
+        slice2,
+        |x, y| slice1_key(x).cmp(slice2_key(y)),
+        |group1, group2| result(slice1_key(&group1[0]), group1, group2),
+    )
+}
+
+/// Like `join_groups`, but with the keys of tuples from either slice
+/// compared by `cmp`, rather than selected and compared directly.
+fn join_groups_by<T1, T2>(
+    mut slice1: &[T1],
+    mut slice2: &[T2],
+    cmp: impl Fn(&T1, &T2) -> Ordering,
+    mut result: impl FnMut(&[T1], &[T2]),
+) {
+    while !slice1.is_empty() && !slice2.is_empty() {
         // If the keys match produce tuples, else advance the smaller key until they might.
-        let key1 = slice1_key(&slice1[0]);
-        let key2 = slice2_key(&slice2[0]);
+        let (first1, first2) = (&slice1[0], &slice2[0]);
 
-        match key1.cmp(key2) {
+        match cmp(first1, first2) {
             Ordering::Less => {
-                slice1 = gallop(slice1, |x| slice1_key(x) < key2);
+                slice1 = gallop(slice1, |x| cmp(x, first2) == Ordering::Less);
             }
             Ordering::Equal => {
                 // Determine the number of matching keys in each slice.
-                let count1 = slice1.iter().take_while(|x| slice1_key(x) == key1).count();
-                let count2 = slice2.iter().take_while(|x| slice2_key(x) == key2).count();
+                let count1 = slice1
+                    .iter()
+                    .take_while(|x| cmp(x, first2) == Ordering::Equal)
+                    .count();
+                let count2 = slice2
+                    .iter()
+                    .take_while(|y| cmp(first1, y) == Ordering::Equal)
+                    .count();
 
-                // Produce results from the cross-product of matches.
-                for index1 in 0..count1 {
-                    for s2 in slice2[..count2].iter() {
-                        result(&key1, &slice1[index1], &s2);
-                    }
-                }
+                result(&slice1[..count1], &slice2[..count2]);
 
                 // Advance slices past this key.
                 slice1 = &slice1[count1..];
                 slice2 = &slice2[count2..];
             }
             Ordering::Greater => {
-                slice2 = gallop(slice2, |x| slice2_key(x) < key1);
+                slice2 = gallop(slice2, |y| cmp(first1, y) == Ordering::Greater);
             }
         }
     }
 }
 
-pub(crate) fn gallop<T>(mut slice: &[T], mut cmp: impl FnMut(&T) -> bool) -> &[T] {
-    // if empty slice, or already >= element, return
-    if !slice.is_empty() && cmp(&slice[0]) {
-        let mut step = 1;
-        while step < slice.len() && cmp(&slice[step]) {
-            slice = &slice[step..];
-            step <<= 1;
-        }
-
-        step >>= 1;
-        while step > 0 {
-            if step < slice.len() && cmp(&slice[step]) {
-                slice = &slice[step..];
+/// Like `join_helper`, but with keys that are computed rather than
+/// borrowed from the tuples.
+fn join_helper_converted<K: Ord, T1, T2>(
+    slice1: &[T1],
+    slice2: &[T2],
+    slice1_key: impl Fn(&T1) -> K,
+    slice2_key: impl Fn(&T2) -> K,
+    mut result: impl FnMut(&K, &T1, &T2),
+) {
+    join_groups_by(
+        slice1,
+        slice2,
+        |x, y| slice1_key(x).cmp(&slice2_key(y)),
+        |group1, group2| {
+            let key = slice1_key(&group1[0]);
+
+            // Produce results from the cross-product of matches.
+            for s1 in group1 {
+                for s2 in group2 {
+                    result(&key, s1, s2);
+                }
             }
-            step >>= 1;
-        }
+        },
+    )
+}
+
+/// Skips past the elements at the start of `slice` for which `cmp`
+/// returns true, and returns the rest of the slice.
+///
+/// The slice must be ordered so that `cmp` returns true for some prefix
+/// of it and false for the remainder; typically `cmp` reports whether an
+/// element lies strictly before some target in a sorted slice. The
+/// search probes exponentially growing steps from the start, and then
+/// narrows down, so it is fast when the prefix is short.
+///
+/// # Examples
+///
+/// ```
+/// let slice = [1, 3, 3, 5, 8, 13];
+///
+/// // The elements from the first one that is at least 4.
+/// assert_eq!(datafrog::gallop(&slice, |&x| x < 4), &[5, 8, 13]);
+/// assert_eq!(datafrog::gallop(&slice, |&x| x < 3), &[3, 3, 5, 8, 13]);
+/// assert!(datafrog::gallop(&slice, |&x| x < 20).is_empty());
+/// ```
+pub fn gallop<T>(slice: &[T], mut cmp: impl FnMut(&T) -> bool) -> &[T] {
+    &slice[gallop_index(slice.len(), |index| cmp(&slice[index]))..]
+}
+
+/// Returns the number of leading indices in `0 .. len` for which `cmp`
+/// returns true, probing as `gallop` does.
+///
+/// This holds the index arithmetic of `gallop`, apart from any slice,
+/// so that it can be checked for lengths that could not be allocated.
+/// `cmp` is only called with indices less than `len`, and no index
+/// computed along the way overflows.
+pub(crate) fn gallop_index(len: usize, mut cmp: impl FnMut(usize) -> bool) -> usize {
+    // if empty, or already >= element, return
+    if len == 0 || !cmp(0) {
+        return 0;
+    }
 
-        slice = &slice[1..]; // advance one, as we always stayed < value
+    // `cmp` holds for `start`, and `start < len`.
+    let mut start = 0;
+    let mut step: usize = 1;
+    while step < len - start && cmp(start + step) {
+        start += step;
+        step = step.saturating_mul(2);
+    }
+
+    step >>= 1;
+    while step > 0 {
+        if step < len - start && cmp(start + step) {
+            start += step;
+        }
+        step >>= 1;
     }
 
-    slice
+    start + 1 // advance one, as we always stayed < value
 }
 
 /// An input that can be used with `from_join`; either a `Variable` or a `Relation`.
@@ -184,6 +861,6 @@ impl<'me, Tuple: Ord> JoinInput<'me, Tuple> for &'me Relation<Tuple> {
     }
 
     fn stable(self) -> Self::StableTuples {
-        std::slice::from_ref(self)
+        core::slice::from_ref(self)
     }
 }
@@ -8,27 +8,50 @@
 //! and the intent is that this code can be dropped in the middle of an otherwise
 //! normal Rust program, run to completion, and then the results extracted as
 //! vectors again.
+//!
+//! The crate only needs `core` and `alloc`, and can be used without the
+//! standard library by disabling the default `std` feature.
 
+#![cfg_attr(not(any(feature = "std", test)), no_std)]
 #![forbid(missing_docs)]
 
-use std::cell::RefCell;
-use std::cmp::Ordering;
-use std::iter::FromIterator;
-use std::rc::Rc;
+extern crate alloc;
+
+use alloc::boxed::Box;
+use alloc::collections::BinaryHeap;
+use alloc::rc::Rc;
+use alloc::string::{String, ToString};
+use alloc::vec;
+use alloc::vec::Vec;
+use core::any::Any;
+use core::cell::{Cell, RefCell};
+use core::cmp::{Ordering, Reverse};
+use core::iter::FromIterator;
+use core::ops::Range;
 
+#[cfg(feature = "bench-support")]
+pub mod bench;
+mod graph;
+mod index;
 mod join;
 mod map;
+#[cfg(feature = "rayon")]
+mod parallel;
 mod test;
+#[cfg(feature = "testing")]
+pub mod testing;
 mod treefrog;
-pub use crate::join::JoinInput;
+pub use crate::graph::{find_cycles, transitive_reduction};
+pub use crate::index::{IndexedRelation, RelationIndex};
+pub use crate::join::{gallop, JoinInput};
 pub use crate::treefrog::{
-    leapjoin,
     extend_anti::ExtendAnti,
     extend_with::ExtendWith,
+    extend_with_index::ExtendWithIndex,
     filter_anti::FilterAnti,
     filter_with::FilterWith,
     filters::{PrefixFilter, ValueFilter},
-    Leaper, Leapers, RelationLeaper,
+    leapjoin, Leaper, Leapers, RelationLeaper,
 };
 
 /// A static, ordered list of key-value pairs.
@@ -36,6 +59,14 @@ pub use crate::treefrog::{
 /// A relation represents a fixed set of key-value pairs. In many places in a
 /// Datalog computation we want to be sure that certain relations are not able
 /// to vary (for example, in antijoins).
+///
+/// The tuples are kept in a `Vec` from the global allocator. Relations do
+/// not take an allocator parameter, as the `allocator_api` it would need
+/// is only available on nightly, and a parameter defaulting to `Global`
+/// would have to be threaded through every operation on relations and
+/// variables. Programs that build many small relations can instead keep
+/// one around and reuse its storage, through `elements` and
+/// `Relation::from_sorted`, or reserve space with `Relation::with_capacity`.
 #[derive(Clone)]
 pub struct Relation<Tuple: Ord> {
     /// Sorted list of distinct tuples.
@@ -68,12 +99,12 @@ impl<Tuple: Ord> Relation<Tuple> {
         // Make sure that elements1 starts with the lower element
         // Will not panic since both collections must have at least 1 element at this point
         if elements1[0] > elements2[0] {
-            std::mem::swap(&mut elements1, &mut elements2);
+            core::mem::swap(&mut elements1, &mut elements2);
         }
 
         // Fast path for when all the new elements are after the exiting ones
         if elements1[elements1.len() - 1] < elements2[0] {
-            elements1.extend(elements2.into_iter());
+            elements1.extend(elements2);
             // println!("fast path");
             return Relation {
                 elements: elements1,
@@ -105,6 +136,82 @@ impl<Tuple: Ord> Relation<Tuple> {
         Relation { elements }
     }
 
+    /// Merges any number of relations into their union.
+    ///
+    /// The relations are merged all at once, repeatedly taking the least
+    /// of their first tuples, rather than pairwise as by `merge`, which
+    /// would copy the tuples of the first relations once per merge.
+    pub fn merge_many(relations: Vec<Relation<Tuple>>) -> Self {
+        let mut relations: Vec<Relation<Tuple>> =
+            relations.into_iter().filter(|r| !r.is_empty()).collect();
+        if relations.len() <= 1 {
+            return relations
+                .pop()
+                .unwrap_or_else(|| Relation::with_capacity(0));
+        }
+
+        let len = relations.iter().map(|relation| relation.len()).sum();
+        let mut sources: Vec<_> = relations
+            .into_iter()
+            .map(|relation| relation.elements.into_iter())
+            .collect();
+
+        // The first remaining tuple of each source, along with its index.
+        let mut heads = BinaryHeap::with_capacity(sources.len());
+        for (index, source) in sources.iter_mut().enumerate() {
+            heads.extend(source.next().map(|tuple| Reverse((tuple, index))));
+        }
+
+        let mut elements: Vec<Tuple> = Vec::with_capacity(len);
+        while let Some(Reverse((tuple, index))) = heads.pop() {
+            heads.extend(sources[index].next().map(|tuple| Reverse((tuple, index))));
+            // Tuples leave the heap in order, so only the last may be equal.
+            if elements.last() != Some(&tuple) {
+                elements.push(tuple);
+            }
+        }
+
+        Relation { elements }
+    }
+
+    /// Adds the tuples of `new` to the relation. Only `new` is sorted,
+    /// after which it is merged into the tuples already present, which
+    /// are sorted already.
+    pub fn extend_sorted_suffix(&mut self, new: Vec<Tuple>) {
+        let elements = core::mem::take(&mut self.elements);
+        *self = Relation { elements }.merge(Relation::from_vec(new));
+    }
+
+    /// Merges two relations into their union, like `merge`, except that
+    /// of two tuples that compare equal, `resolve` is given the one from
+    /// `self` and the one from `other`, and its result is kept.
+    ///
+    /// This is only useful for tuples whose ordering ignores part of
+    /// them, such as a version, so that equal tuples may differ. The
+    /// result of `resolve` must compare equal to both of its arguments.
+    pub fn merge_resolving(self, other: Self, resolve: impl Fn(&Tuple, &Tuple) -> Tuple) -> Self {
+        let mut elements = Vec::with_capacity(self.len() + other.len());
+        let mut elements1 = self.elements.into_iter().peekable();
+        let mut elements2 = other.elements.into_iter().peekable();
+
+        while let (Some(x), Some(y)) = (elements1.peek(), elements2.peek()) {
+            match x.cmp(y) {
+                Ordering::Less => elements.push(elements1.next().unwrap()),
+                Ordering::Greater => elements.push(elements2.next().unwrap()),
+                Ordering::Equal => {
+                    let (x, y) = (elements1.next().unwrap(), elements2.next().unwrap());
+                    elements.push(resolve(&x, &y));
+                }
+            }
+        }
+
+        // At most one of the lists has elements left.
+        elements.extend(elements1);
+        elements.extend(elements2);
+
+        Relation { elements }
+    }
+
     /// Creates a `Relation` from the elements of the `iterator`.
     ///
     /// Same as the `from_iter` method from `std::iter::FromIterator` trait.
@@ -134,7 +241,13 @@ impl<Tuple: Ord> Relation<Tuple> {
         input2: &Relation<(Key, Val2)>,
         mut logic: impl FnMut(&Key, &Val1, &Val2) -> Tuple,
     ) -> Self {
-        join::join_into_relation(input1, input2, |(k, _)| k, |(k, _)| k, |k, v1, v2| logic(k, &v1.1, &v2.1))
+        join::join_into_relation(
+            input1,
+            input2,
+            |(k, _)| k,
+            |(k, _)| k,
+            |k, v1, v2| logic(k, &v1.1, &v2.1),
+        )
     }
 
     /// Like `from_join`, but lets the caller choose how keys are selected.
@@ -168,12 +281,653 @@ impl<Tuple: Ord> Relation<Tuple> {
         input.iter().map(logic).collect()
     }
 
+    /// Applies `logic` to each tuple, and collects the results into a
+    /// new relation, sorted and deduplicated.
+    ///
+    /// This is the counterpart of `Variable::from_map` for a relation
+    /// that is already complete, such as the result of an iteration,
+    /// and is the same as `Relation::from_map(self, logic)`.
+    pub fn map<U: Ord>(&self, logic: impl FnMut(&Tuple) -> U) -> Relation<U> {
+        Relation::from_map(self, logic)
+    }
+
+    /// Applies `logic` to each tuple, reusing the storage of `self` and
+    /// without sorting the results again.
+    ///
+    /// `logic` must be strictly monotone, mapping tuples in order to
+    /// results in order, as adding a constant offset to node ids does;
+    /// this is only checked in debug builds. Use `map` for other
+    /// transforms.
+    pub fn map_monotone(self, logic: impl Fn(Tuple) -> Tuple) -> Relation<Tuple> {
+        let result = Relation {
+            elements: self.elements.into_iter().map(logic).collect(),
+        };
+        debug_assert!(
+            result.is_sorted_and_distinct(),
+            "map_monotone given a transform that does not preserve the order of tuples"
+        );
+        result
+    }
+
+    /// Expands each tuple into the zero or more results of `f`, and
+    /// collects them into a new relation, sorted and deduplicated. The
+    /// results of `f` may borrow from the tuple.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use datafrog::Relation;
+    ///
+    /// let adjacency: Relation<(u32, Vec<u32>)> =
+    ///     vec![(1, vec![2, 3]), (2, vec![]), (3, vec![1])].into();
+    /// let edges = adjacency.flat_map(|(a, bs)| bs.iter().map(move |&b| (*a, b)));
+    /// assert_eq!(edges.elements, vec![(1, 2), (1, 3), (3, 1)]);
+    /// ```
+    pub fn flat_map<'a, U: Ord, I: IntoIterator<Item = U>>(
+        &'a self,
+        f: impl FnMut(&'a Tuple) -> I,
+    ) -> Relation<U> {
+        self.iter().flat_map(f).collect()
+    }
+
+    /// Projects each tuple with `f`, for example onto some of its
+    /// fields, and collects the results into a new relation.
+    ///
+    /// This is `map` under the name for its most common use. As the
+    /// projection may change the order of the tuples, and map distinct
+    /// tuples to the same result, the results are sorted and
+    /// deduplicated.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use datafrog::Relation;
+    ///
+    /// let relation: Relation<(u32, u32, u32)> = vec![(1, 2, 3), (1, 5, 3), (0, 9, 4)].into();
+    /// let projected = relation.project(|&(a, _, c)| (a, c));
+    /// assert_eq!(projected.elements, vec![(0, 4), (1, 3)]);
+    /// ```
+    pub fn project<U: Ord>(&self, f: impl FnMut(&Tuple) -> U) -> Relation<U> {
+        self.map(f)
+    }
+
+    /// Like `from_map`, but only keeps the results for which `f` returns
+    /// `Some`, and relies on `f` preserving the order of the tuples.
+    ///
+    /// The results are not sorted, which makes this cheaper than
+    /// collecting them into a new relation. That they are already in
+    /// order is only checked in debug builds.
+    pub fn filter_map_ordered<R: Ord>(&self, f: impl Fn(&Tuple) -> Option<R>) -> Relation<R> {
+        let mut elements: Vec<R> = self.elements.iter().filter_map(f).collect();
+        debug_assert!(
+            elements.windows(2).all(|w| w[0] <= w[1]),
+            "filter_map_ordered produced tuples out of order"
+        );
+        elements.dedup();
+        Relation { elements }
+    }
+
+    /// Reports whether the relation contains `tuple`, by binary search.
+    pub fn contains(&self, tuple: &Tuple) -> bool {
+        self.elements.binary_search(tuple).is_ok()
+    }
+
+    /// Returns the tuples whose key, as selected by `key_fn`, is `key`.
+    ///
+    /// The keys must be ordered consistently with the tuples, for
+    /// example by selecting a leading field, so that the matching tuples
+    /// are contiguous.
+    pub fn get_range<K: Ord>(&self, key: &K, key_fn: impl Fn(&Tuple) -> &K) -> &[Tuple] {
+        &self.elements[self.key_range(key, key_fn)]
+    }
+
+    /// Like `get_range`, but returns the positions of the tuples whose
+    /// key is `key`, for indexing into data kept alongside the relation.
+    /// If there are none, the range is empty, and starts where such
+    /// tuples would be inserted.
+    pub fn key_range<K: Ord>(&self, key: &K, key_fn: impl Fn(&Tuple) -> &K) -> Range<usize> {
+        let len = self.elements.len();
+        let start = join::gallop(&self.elements, |x| key_fn(x) < key);
+        let rest = join::gallop(start, |x| key_fn(x) <= key);
+        (len - start.len())..(len - rest.len())
+    }
+
+    /// Returns an iterator over the runs of tuples that share a key, as
+    /// selected by `key_fn`, together with that key.
+    ///
+    /// The keys must be ordered consistently with the tuples, for
+    /// example by selecting a leading field, so that the tuples sharing
+    /// a key are contiguous.
+    pub fn group_by<'a, K: Ord + 'a>(
+        &'a self,
+        key_fn: impl Fn(&Tuple) -> &K + 'a,
+    ) -> impl Iterator<Item = (&'a K, &'a [Tuple])> + 'a {
+        let mut slice = &self.elements[..];
+        core::iter::from_fn(move || {
+            let key = key_fn(slice.first()?);
+            let count = slice.iter().take_while(|x| key_fn(x) == key).count();
+            let (group, rest) = slice.split_at(count);
+            slice = rest;
+            Some((key, group))
+        })
+    }
+
+    /// Distributes the tuples into `n` relations, placing each in the
+    /// one numbered by `bucket`. Relations to which no tuple is assigned
+    /// are empty.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `n` is zero, or if `bucket` returns a number that is
+    /// not less than `n`.
+    pub fn partition(&self, n: usize, bucket: impl Fn(&Tuple) -> usize) -> Vec<Relation<Tuple>>
+    where
+        Tuple: Clone,
+    {
+        assert!(n > 0, "partition needs at least one bucket");
+
+        // The tuples are visited in order, so each bucket stays sorted.
+        let mut buckets = vec![Vec::new(); n];
+        for tuple in self.elements.iter() {
+            let index = bucket(tuple);
+            assert!(
+                index < n,
+                "bucket {} is out of bounds for {} buckets",
+                index,
+                n
+            );
+            buckets[index].push(tuple.clone());
+        }
+
+        buckets
+            .into_iter()
+            .map(|elements| Relation { elements })
+            .collect()
+    }
+
+    /// Keeps one tuple for each key, as selected by `key_fn`: the last,
+    /// and so greatest, of the tuples with that key. The keys must be
+    /// ordered consistently with the tuples, as for `group_by`.
+    pub fn last_per_key<K: Ord>(&self, key_fn: impl Fn(&Tuple) -> &K) -> Relation<Tuple>
+    where
+        Tuple: Clone,
+    {
+        let elements = self
+            .group_by(key_fn)
+            .map(|(_, group)| group[group.len() - 1].clone())
+            .collect();
+        Relation { elements }
+    }
+
+    /// Folds the tuples sharing each key, as selected by `key_fn`, into
+    /// an aggregate, starting from `init` and applying `step` to each
+    /// tuple in turn.
+    ///
+    /// Aggregates such as minima are not monotonic, and so cannot be
+    /// computed by a variable during an iteration; instead, compute
+    /// them from the completed relation. The keys must be ordered
+    /// consistently with the tuples, as for `group_by`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use datafrog::Relation;
+    ///
+    /// let distances: Relation<(u32, u32)> = vec![(1, 5), (1, 3), (2, 7)].into();
+    /// let shortest =
+    ///     distances.reduce_by(|(node, _)| node, || u32::MAX, |min, &(_, d)| min.min(d));
+    /// assert_eq!(shortest.elements, vec![(1, 3), (2, 7)]);
+    /// ```
+    pub fn reduce_by<K: Ord + Clone, A: Ord>(
+        &self,
+        key_fn: impl Fn(&Tuple) -> &K,
+        init: impl Fn() -> A,
+        mut step: impl FnMut(A, &Tuple) -> A,
+    ) -> Relation<(K, A)> {
+        let elements = self
+            .group_by(key_fn)
+            .map(|(key, group)| (key.clone(), group.iter().fold(init(), &mut step)))
+            .collect();
+
+        // Each key occurs once, in increasing order.
+        Relation { elements }
+    }
+
+    /// Returns the tuples present in both this relation and `other`.
+    pub fn intersect(&self, other: &Self) -> Relation<Tuple>
+    where
+        Tuple: Clone,
+    {
+        let mut elements = Vec::new();
+        let (mut slice1, mut slice2) = (&self.elements[..], &other.elements[..]);
+        while let (Some(x), Some(y)) = (slice1.first(), slice2.first()) {
+            match x.cmp(y) {
+                Ordering::Less => slice1 = &slice1[1..],
+                Ordering::Greater => slice2 = &slice2[1..],
+                Ordering::Equal => {
+                    elements.push(x.clone());
+                    slice1 = &slice1[1..];
+                    slice2 = &slice2[1..];
+                }
+            }
+        }
+
+        // Both inputs are sorted and distinct, and so is the result.
+        Relation { elements }
+    }
+
+    /// Returns the tuples present in this relation but not in `other`.
+    pub fn difference(&self, other: &Self) -> Relation<Tuple>
+    where
+        Tuple: Clone,
+    {
+        let mut elements = Vec::new();
+        let (mut slice1, mut slice2) = (&self.elements[..], &other.elements[..]);
+        while let Some(x) = slice1.first() {
+            match slice2.first().map(|y| x.cmp(y)) {
+                Some(Ordering::Greater) => slice2 = &slice2[1..],
+                Some(Ordering::Equal) => {
+                    slice1 = &slice1[1..];
+                    slice2 = &slice2[1..];
+                }
+                Some(Ordering::Less) | None => {
+                    elements.push(x.clone());
+                    slice1 = &slice1[1..];
+                }
+            }
+        }
+
+        Relation { elements }
+    }
+
+    /// Pairs each tuple with its position in the relation.
+    ///
+    /// As the positions increase along with the tuples, the result is
+    /// already sorted and needs no further sorting.
+    pub fn enumerate(&self) -> Relation<(usize, Tuple)>
+    where
+        Tuple: Clone,
+    {
+        Relation {
+            elements: self.elements.iter().cloned().enumerate().collect(),
+        }
+    }
+
+    /// Collects the tuples of `iterator` into a vector, dropping every
+    /// tuple equal to an earlier one but otherwise keeping the order in
+    /// which they were produced.
+    ///
+    /// Unlike a `Relation`, the result is not sorted; this is meant for
+    /// presenting results in some order other than the sorted one.
+    pub fn stable_dedup_by_insertion(iterator: impl IntoIterator<Item = Tuple>) -> Vec<Tuple> {
+        let elements: Vec<Tuple> = iterator.into_iter().collect();
+
+        // A stable sort keeps equal tuples in insertion order, so the
+        // first index of each run is the first occurrence.
+        let mut order: Vec<usize> = (0..elements.len()).collect();
+        order.sort_by(|&i, &j| elements[i].cmp(&elements[j]));
+
+        let mut keep = vec![false; elements.len()];
+        for (position, &index) in order.iter().enumerate() {
+            keep[index] = position == 0 || elements[order[position - 1]] != elements[index];
+        }
+
+        elements
+            .into_iter()
+            .zip(keep)
+            .filter_map(|(tuple, keep)| if keep { Some(tuple) } else { None })
+            .collect()
+    }
+
+    /// Creates an empty `Relation` with space reserved for `capacity`
+    /// tuples.
+    pub fn with_capacity(capacity: usize) -> Self {
+        Relation {
+            elements: Vec::with_capacity(capacity),
+        }
+    }
+
+    /// Returns the number of tuples the relation can hold without
+    /// reallocating.
+    pub fn capacity(&self) -> usize {
+        self.elements.capacity()
+    }
+
+    /// Releases the space reserved beyond the tuples of the relation.
+    pub fn shrink_to_fit(&mut self) {
+        self.elements.shrink_to_fit();
+    }
+
     /// Creates a `Relation` from a vector of tuples.
     pub fn from_vec(mut elements: Vec<Tuple>) -> Self {
         elements.sort();
         elements.dedup();
         Relation { elements }
     }
+
+    /// Creates a `Relation` from a vector of tuples that is already
+    /// sorted and free of duplicates, such as the elements of another
+    /// relation, without sorting it again.
+    ///
+    /// This is only checked in debug builds. Every operation on
+    /// relations relies on the tuples being sorted and distinct, so a
+    /// relation built from other input silently produces wrong results
+    /// in joins, antijoins, merges and lookups.
+    pub fn from_sorted(elements: Vec<Tuple>) -> Self {
+        debug_assert!(
+            elements.windows(2).all(|w| w[0] < w[1]),
+            "from_sorted given tuples that are not sorted and distinct"
+        );
+        Relation { elements }
+    }
+
+    /// Returns `true` if the tuples are sorted and free of duplicates,
+    /// as every operation on relations expects.
+    pub fn is_sorted_and_distinct(&self) -> bool {
+        self.elements.windows(2).all(|w| w[0] < w[1])
+    }
+
+    /// Panics if the tuples are not sorted and free of duplicates,
+    /// naming the first pair of neighbouring tuples that is out of order.
+    ///
+    /// This is meant for relations built directly from their `elements`,
+    /// which are not sorted for you.
+    pub fn assert_valid(&self)
+    where
+        Tuple: core::fmt::Debug,
+    {
+        if let Some(index) = self.elements.windows(2).position(|w| w[0] >= w[1]) {
+            panic!(
+                "relation is not sorted and distinct: {:?} at index {} is followed by {:?}",
+                self.elements[index],
+                index,
+                self.elements[index + 1],
+            );
+        }
+    }
+}
+
+#[cfg(feature = "rayon")]
+impl<Tuple: Ord + Send> Relation<Tuple> {
+    /// Like `from_vec`, but sorts and deduplicates large inputs on
+    /// several threads.
+    ///
+    /// Inputs of fewer than 100,000 tuples are handled as by `from_vec`,
+    /// as for them the cost of spreading the work across threads
+    /// outweighs the benefit.
+    pub fn from_vec_par(mut elements: Vec<Tuple>) -> Self {
+        if elements.len() < parallel::THRESHOLD {
+            return Relation::from_vec(elements);
+        }
+        parallel::sort_dedup(&mut elements);
+        Relation { elements }
+    }
+
+    /// Like `merge`, but sorts the union of large relations on several
+    /// threads, rather than merging them on one.
+    ///
+    /// Relations with fewer than 100,000 tuples between them are merged
+    /// as by `merge`.
+    pub fn merge_par(self, other: Self) -> Self {
+        if self.len() + other.len() < parallel::THRESHOLD {
+            return self.merge(other);
+        }
+        let mut elements = self.elements;
+        elements.extend(other.elements);
+        parallel::sort_dedup(&mut elements);
+        Relation { elements }
+    }
+}
+
+impl<Key: Ord, Val: Ord> Relation<(Key, Val)> {
+    /// Returns an iterator over the values paired with `key`, in order,
+    /// which is empty if the key is absent.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use datafrog::Relation;
+    ///
+    /// let edges: Relation<(u32, u32)> = vec![(1, 3), (2, 4), (1, 2)].into();
+    /// assert_eq!(edges.values_for(&1).collect::<Vec<_>>(), vec![&2, &3]);
+    /// assert_eq!(edges.values_for(&5).count(), 0);
+    /// ```
+    pub fn values_for<'a>(&'a self, key: &Key) -> impl Iterator<Item = &'a Val> + 'a {
+        self.get_range(key, |(k, _)| k).iter().map(|(_, v)| v)
+    }
+
+    /// Joins this relation with `other`, producing for each key present
+    /// in both the values from each side, grouped into vectors.
+    ///
+    /// Unlike `from_join`, this does not produce the cross-product of
+    /// the matching values, but each output tuple owns a copy of every
+    /// value sharing its key. Relations with many values per key will
+    /// therefore produce large tuples, and comparing those tuples (for
+    /// example when merging the result into another relation) compares
+    /// the whole vectors.
+    #[allow(clippy::type_complexity)]
+    pub fn join_grouped<Val2: Ord + Clone>(
+        &self,
+        other: &Relation<(Key, Val2)>,
+    ) -> Relation<(Key, (Vec<Val>, Vec<Val2>))>
+    where
+        Key: Clone,
+        Val: Clone,
+    {
+        let mut elements = Vec::new();
+        join::join_groups(
+            &self.elements,
+            &other.elements,
+            |(k, _)| k,
+            |(k, _)| k,
+            |k, group1, group2| {
+                let vals1 = group1.iter().map(|(_, v1)| v1.clone()).collect();
+                let vals2 = group2.iter().map(|(_, v2)| v2.clone()).collect();
+                elements.push((k.clone(), (vals1, vals2)));
+            },
+        );
+
+        // Keys are produced in increasing order and each at most once.
+        Relation { elements }
+    }
+
+    /// Joins this relation with `other`, applying `logic` to each key
+    /// and pair of values. This is the same as
+    /// `Relation::from_join(self, other, logic)`.
+    pub fn join_with<Val2: Ord, Result: Ord>(
+        &self,
+        other: &Relation<(Key, Val2)>,
+        logic: impl FnMut(&Key, &Val, &Val2) -> Result,
+    ) -> Relation<Result> {
+        Relation::from_join(self, other, logic)
+    }
+
+    /// Joins this relation with `other`, like `Relation::from_join`, and
+    /// also counts the tuples of this relation whose key has no match in
+    /// `other`.
+    pub fn join_with_miss_count<Val2: Ord, Result: Ord>(
+        &self,
+        other: &Relation<(Key, Val2)>,
+        mut logic: impl FnMut(&Key, &Val, &Val2) -> Result,
+    ) -> (Relation<Result>, usize) {
+        let mut results = Vec::new();
+        let mut matched = 0;
+        join::join_groups(
+            &self.elements,
+            &other.elements,
+            |(k, _)| k,
+            |(k, _)| k,
+            |k, group1, group2| {
+                matched += group1.len();
+                for (_, v1) in group1 {
+                    for (_, v2) in group2 {
+                        results.push(logic(k, v1, v2));
+                    }
+                }
+            },
+        );
+
+        (Relation::from_vec(results), self.len() - matched)
+    }
+
+    /// Keeps a single tuple for each key, choosing among the values for
+    /// that key with `choose`, which reports whether its first argument
+    /// is preferred over its second.
+    ///
+    /// When neither of two values is preferred, the smaller is kept.
+    pub fn retain_one_per_key(
+        mut self,
+        choose: impl Fn(&Val, &Val) -> bool,
+    ) -> Relation<(Key, Val)> {
+        let mut elements: Vec<(Key, Val)> = Vec::new();
+        for (key, val) in self.elements.drain(..) {
+            match elements.last_mut() {
+                Some(last) if last.0 == key => {
+                    if choose(&val, &last.1) {
+                        last.1 = val;
+                    }
+                }
+                _ => elements.push((key, val)),
+            }
+        }
+
+        // One tuple remains per key, in order of the keys.
+        Relation { elements }
+    }
+
+    /// Returns the keys that appear with more than one value, which are
+    /// those for which the relation is not a function.
+    pub fn keys_with_multiple_values(&self) -> Relation<Key>
+    where
+        Key: Clone,
+    {
+        let mut keys = Vec::new();
+        for pair in self.elements.windows(2) {
+            // Tuples are distinct, so equal keys imply distinct values.
+            if pair[0].0 == pair[1].0 && keys.last() != Some(&pair[0].0) {
+                keys.push(pair[0].0.clone());
+            }
+        }
+        Relation { elements: keys }
+    }
+
+    /// Keeps only the tuples whose key lies within `range`.
+    pub fn retain_key_range(mut self, range: Range<Key>) -> Self {
+        let start = self.elements.partition_point(|(k, _)| k < &range.start);
+        let end = self.elements.partition_point(|(k, _)| k < &range.end);
+        self.elements.truncate(end.max(start));
+        self.elements.drain(..start);
+        self
+    }
+
+    /// Returns the tuples whose key is not present in `keys`.
+    ///
+    /// This is the one-shot counterpart of `Variable::from_antijoin`
+    /// for relations that are already complete; see also `remove_keys`,
+    /// which consumes the relation instead of cloning its tuples, and
+    /// `Relation::from_antijoin`, which transforms them.
+    pub fn antijoin(&self, keys: &Relation<Key>) -> Self
+    where
+        Key: Clone,
+        Val: Clone,
+    {
+        let mut keys = &keys[..];
+        let elements = self
+            .iter()
+            .filter(|(key, _)| {
+                keys = join::gallop(keys, |k| k < key);
+                keys.first() != Some(key)
+            })
+            .cloned()
+            .collect();
+
+        // A subsequence of sorted, distinct tuples is sorted and distinct.
+        Relation { elements }
+    }
+
+    /// Removes every tuple whose key is present in `keys`.
+    ///
+    /// This is like `from_antijoin`, except that it retains the tuples
+    /// themselves and needs no iteration.
+    pub fn remove_keys(mut self, keys: &Relation<Key>) -> Self {
+        let mut keys = &keys[..];
+        self.elements.retain(|(key, _)| {
+            keys = join::gallop(keys, |k| k < key);
+            keys.first() != Some(key)
+        });
+        self
+    }
+}
+
+impl<Val: Ord> Relation<(u64, Val)> {
+    /// Returns the keys in `range` for which the relation has no tuple,
+    /// in increasing order.
+    pub fn missing_keys(&self, range: Range<u64>) -> Vec<u64> {
+        let mut slice = &self.elements[..];
+        let mut missing = Vec::new();
+        for key in range {
+            slice = join::gallop(slice, |(k, _)| *k < key);
+            if slice.first().map(|(k, _)| *k) != Some(key) {
+                missing.push(key);
+            }
+        }
+        missing
+    }
+}
+
+impl<Val: Ord + Clone> Relation<(usize, Val)> {
+    /// Returns a vector of `len` values, holding at each index the value
+    /// for that key, or `default` for keys without a value.
+    ///
+    /// Each key is assumed to have a single value; of several values for
+    /// a key, the greatest is kept.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a key is not less than `len`.
+    pub fn tabulate(&self, len: usize, default: Val) -> Vec<Val> {
+        let mut table = vec![default; len];
+        for (key, val) in self.iter() {
+            assert!(
+                *key < len,
+                "key {} is out of bounds for length {}",
+                key,
+                len
+            );
+            table[*key] = val.clone();
+        }
+        table
+    }
+}
+
+impl<T: Ord + Clone, const N: usize> Relation<[T; N]> {
+    /// Reorders the columns of each tuple, so that column `i` of the
+    /// result is column `perm[i]` of the input, and re-sorts the tuples.
+    ///
+    /// This is useful to bring a different column to the front before
+    /// using the relation as the input to a join on that column.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `perm` is not a permutation of `0..N`.
+    pub fn permute_columns(self, perm: [usize; N]) -> Self {
+        let mut seen = [false; N];
+        for &column in perm.iter() {
+            assert!(
+                column < N && !seen[column],
+                "{:?} is not a permutation",
+                perm
+            );
+            seen[column] = true;
+        }
+
+        Relation::from_vec(
+            self.elements
+                .into_iter()
+                .map(|tuple| core::array::from_fn(|i| tuple[perm[i]].clone()))
+                .collect(),
+        )
+    }
 }
 
 impl<Tuple: Ord> From<Vec<Tuple>> for Relation<Tuple> {
@@ -200,7 +954,59 @@ impl<'tuple, Tuple: 'tuple + Copy + Ord> FromIterator<&'tuple Tuple> for Relatio
     }
 }
 
-impl<Tuple: Ord> std::ops::Deref for Relation<Tuple> {
+#[cfg(feature = "serde")]
+impl<Tuple: Ord + serde::Serialize> serde::Serialize for Relation<Tuple> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.elements.serialize(serializer)
+    }
+}
+
+/// The tuples are sorted and deduplicated after deserialization, rather
+/// than trusting the input to already be.
+#[cfg(feature = "serde")]
+impl<'de, Tuple: Ord + serde::Deserialize<'de>> serde::Deserialize<'de> for Relation<Tuple> {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Vec::deserialize(deserializer).map(Relation::from_vec)
+    }
+}
+
+/// Iterates over the tuples of a relation, in order.
+///
+/// # Examples
+///
+/// ```
+/// use datafrog::Relation;
+///
+/// let relation: Relation<(u32, u32)> = vec![(2, 1), (1, 2)].into();
+///
+/// let mut sources = Vec::new();
+/// for (source, _) in &relation {
+///     sources.push(*source);
+/// }
+/// assert_eq!(sources, vec![1, 2]);
+///
+/// let tuples: Vec<(u32, u32)> = relation.into_iter().collect();
+/// assert_eq!(tuples, vec![(1, 2), (2, 1)]);
+/// ```
+impl<Tuple: Ord> IntoIterator for Relation<Tuple> {
+    type Item = Tuple;
+    type IntoIter = alloc::vec::IntoIter<Tuple>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.elements.into_iter()
+    }
+}
+
+impl<'a, Tuple: Ord> IntoIterator for &'a Relation<Tuple> {
+    type Item = &'a Tuple;
+    type IntoIter = core::slice::Iter<'a, Tuple>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.elements.iter()
+    }
+}
+
+impl<Tuple: Ord> core::ops::Deref for Relation<Tuple> {
     type Target = [Tuple];
     fn deref(&self) -> &Self::Target {
         &self.elements[..]
@@ -212,55 +1018,831 @@ impl<Tuple: Ord> std::ops::Deref for Relation<Tuple> {
 /// An `Iteration` tracks monotonic variables, and monitors their progress.
 /// It can inform the user if they have ceased changing, at which point the
 /// computation should be done.
+///
+/// An `Iteration` is cheap to create, so a computation that must be run
+/// many times, such as an inner fixpoint for each tuple of some outer
+/// relation, can simply create a new one each time; see
+/// `nested_iteration`.
 #[derive(Default)]
 pub struct Iteration<'v> {
     variables: Vec<Box<dyn VariableTrait + 'v>>,
+    observers: Vec<DeltaObserver<'v>>,
+    recorders: Vec<InsertRecorder<'v>>,
+    trace: Vec<Vec<RecordedInsert>>,
+    /// For each stratum created by `new_stratum`, whether it has reached
+    /// a fixpoint.
+    strata: Vec<Rc<Cell<bool>>>,
+    /// Whether this iteration has reached a fixpoint, if it is a stratum.
+    at_fixpoint: Option<Rc<Cell<bool>>>,
     round: u32,
+    config: IterationConfig,
+    #[cfg(feature = "trace")]
+    tracing: bool,
 }
 
+/// Settings for the variables of an `Iteration`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct IterationConfig {
+    /// How eagerly each variable merges its stable batches.
+    ///
+    /// When recent tuples become stable, they are merged with the last
+    /// stable batch as long as that batch has at most `merge_factor`
+    /// times as many tuples, which keeps the number of batches
+    /// logarithmic in the number of tuples. Larger factors merge more
+    /// eagerly, and so keep fewer batches but re-merge large batches
+    /// more often; zero disables merging altogether. The default is 2.
+    pub merge_factor: usize,
+}
+
+impl Default for IterationConfig {
+    fn default() -> Self {
+        IterationConfig { merge_factor: 2 }
+    }
+}
+
+/// A reference to a tuple whose type has been erased, as reported by
+/// `Iteration::changed_observing_deltas`. Use `downcast_ref` to recover
+/// the tuple.
+pub type ErasedTuple<'a> = &'a dyn Any;
+
+/// A relation inserted into a variable, as recorded by `Iteration::record`.
+#[derive(Clone)]
+pub struct RecordedInsert {
+    /// The name of the variable the relation was inserted into.
+    pub variable: String,
+    /// The number of tuples in the inserted relation.
+    pub tuples: usize,
+    /// A copy of the inserted relation, with its tuple type erased.
+    relation: Rc<dyn Any>,
+}
+
+impl RecordedInsert {
+    /// Returns the inserted relation, or `None` if its tuples are not
+    /// of type `Tuple`.
+    pub fn relation<Tuple: Ord + Any>(&self) -> Option<&Relation<Tuple>> {
+        self.relation.downcast_ref()
+    }
+}
+
+impl core::fmt::Debug for RecordedInsert {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("RecordedInsert")
+            .field("variable", &self.variable)
+            .field("tuples", &self.tuples)
+            .finish_non_exhaustive()
+    }
+}
+
+/// The progress made by the variables of an iteration in one round, as
+/// reported by `Iteration::step`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StepReport {
+    /// The round that was started, as returned by `Iteration::round`.
+    pub round: usize,
+    /// The total number of tuples that became recent in this round.
+    pub new_tuples: usize,
+    /// The progress of each variable, in the order they were created.
+    pub variables: Vec<VariableDelta>,
+}
+
+impl StepReport {
+    /// Reports whether any of the variables changed, as `changed` would.
+    pub fn changed(&self) -> bool {
+        self.variables.iter().any(|delta| delta.changed)
+    }
+}
+
+/// The progress made by one variable in a round; see `StepReport`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct VariableDelta {
+    /// The name of the variable, or an empty name if it has none.
+    pub name: String,
+    /// Whether the variable changed.
+    pub changed: bool,
+    /// The number of tuples that became recent in this round.
+    pub new_tuples: usize,
+}
+
+/// The tuples of a variable, saved by `Variable::snapshot` to be
+/// restored by `Iteration::variable_from_snapshot` in a later run.
+#[derive(Clone)]
+pub struct RelationSnapshot<Tuple: Ord> {
+    relation: Relation<Tuple>,
+}
+
+impl<Tuple: Ord> RelationSnapshot<Tuple> {
+    /// Returns the saved tuples.
+    pub fn relation(&self) -> &Relation<Tuple> {
+        &self.relation
+    }
+
+    /// Consumes the snapshot, returning the saved tuples.
+    pub fn into_relation(self) -> Relation<Tuple> {
+        self.relation
+    }
+}
+
+/// Creates a snapshot from tuples saved elsewhere, such as those of a
+/// snapshot that was written to disk.
+impl<Tuple: Ord> From<Relation<Tuple>> for RelationSnapshot<Tuple> {
+    fn from(relation: Relation<Tuple>) -> Self {
+        RelationSnapshot { relation }
+    }
+}
+
+/// The error returned by `Iteration::changed_with_limit` when the
+/// variables are still changing after the given number of rounds.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct IterationError {
+    /// The number of rounds that were allowed.
+    pub limit: usize,
+}
+
+impl core::fmt::Display for IterationError {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "no fixpoint was reached within {} rounds", self.limit)
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for IterationError {}
+
+/// Reports the recent tuples of one observed variable to a callback.
+type DeltaObserver<'v> = Box<dyn Fn(&mut dyn FnMut(&str, &[ErasedTuple])) + 'v>;
+
+/// Appends the relations inserted into a variable since it last changed.
+type InsertRecorder<'v> = Box<dyn Fn(&mut Vec<RecordedInsert>) + 'v>;
+
 impl<'v> Iteration<'v> {
     /// Create a new iterative context.
     pub fn new() -> Self {
         Self::default()
     }
 
+    /// Creates a new iterative context whose variables use `config`.
+    pub fn with_config(config: IterationConfig) -> Self {
+        Iteration {
+            config,
+            ..Self::default()
+        }
+    }
+
+    /// Creates a new iterative context for a stratum, the variables of
+    /// which are to reach a fixpoint before this iteration continues.
+    ///
+    /// Rules that negate a relation, with `from_antijoin` or the
+    /// `ExtendAnti` and `FilterAnti` leapers, are only sound once that
+    /// relation is fully computed. Computing it in a stratum of its own,
+    /// and completing its variables into relations before they are used,
+    /// ensures that they can no longer change. The stratum uses the
+    /// configuration of this iteration.
+    ///
+    /// # Panics
+    ///
+    /// Once a stratum has been created, `changed` panics until the
+    /// stratum has reached a fixpoint, that is, until its own `changed`
+    /// has returned `false`.
+    ///
+    /// # Examples
+    ///
+    /// This example finds the nodes that are not reachable from node 1.
+    ///
+    /// ```
+    /// use datafrog::{Iteration, Relation};
+    ///
+    /// let nodes: Relation<(u32, ())> = (1..=5).map(|n| (n, ())).collect();
+    /// let edges: Relation<(u32, u32)> = vec![(1, 2), (2, 3), (4, 5)].into();
+    ///
+    /// let mut iteration = Iteration::new();
+    ///
+    /// let reachable = {
+    ///     let mut stratum = iteration.new_stratum();
+    ///     let reachable = stratum.variable::<(u32, ())>();
+    ///     reachable.extend(vec![(1, ())]);
+    ///     while stratum.changed() {
+    ///         reachable.from_join(&reachable, &edges, |_, &(), &next| (next, ()));
+    ///     }
+    ///     reachable.complete()
+    /// };
+    /// let reachable: Relation<u32> = reachable.iter().map(|&(n, ())| n).collect();
+    ///
+    /// let all = iteration.variable::<(u32, ())>();
+    /// let unreachable = iteration.variable::<u32>();
+    /// all.insert(nodes);
+    /// while iteration.changed() {
+    ///     unreachable.from_antijoin(&all, &reachable, |&n, &()| n);
+    /// }
+    ///
+    /// assert_eq!(unreachable.complete().elements, vec![4, 5]);
+    /// ```
+    pub fn new_stratum(&mut self) -> Iteration<'v> {
+        let at_fixpoint = Rc::new(Cell::new(false));
+        self.strata.push(at_fixpoint.clone());
+        Iteration {
+            at_fixpoint: Some(at_fixpoint),
+            config: self.config,
+            #[cfg(feature = "trace")]
+            tracing: self.tracing,
+            ..Self::default()
+        }
+    }
+
     /// Reports whether any of the monitored variables have changed since
     /// the most recent call.
     pub fn changed(&mut self) -> bool {
+        self.changed_each().contains(&true)
+    }
+
+    /// Like `changed`, but returns the name of each variable, in the
+    /// order they were created, together with whether it changed.
+    /// Variables created without a name are reported by an empty name.
+    pub fn changed_detailed(&mut self) -> Vec<(String, bool)> {
+        let changed = self.changed_each();
+        let names = self
+            .variables
+            .iter()
+            .map(|variable| variable.name().to_string());
+        names.zip(changed).collect()
+    }
+
+    /// Like `changed`, but reports the progress of each variable in the
+    /// round it starts, for computations that interleave rounds with
+    /// other work and want to show their progress as they go.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use datafrog::Iteration;
+    ///
+    /// let mut iteration = Iteration::new();
+    /// let numbers = iteration.variable_named::<u32>("numbers");
+    /// numbers.extend(vec![1, 2]);
+    ///
+    /// let report = iteration.step();
+    /// assert!(report.changed());
+    /// assert_eq!(report.new_tuples, 2);
+    /// assert_eq!(report.variables[0].name, "numbers");
+    ///
+    /// assert!(!iteration.step().changed());
+    /// ```
+    pub fn step(&mut self) -> StepReport {
+        let changed = self.changed_each();
+        let variables: Vec<VariableDelta> = self
+            .variables
+            .iter()
+            .zip(changed)
+            .map(|(variable, changed)| VariableDelta {
+                name: variable.name().to_string(),
+                changed,
+                new_tuples: variable.recent_len(),
+            })
+            .collect();
+
+        StepReport {
+            round: self.round(),
+            new_tuples: variables.iter().map(|delta| delta.new_tuples).sum(),
+            variables,
+        }
+    }
+
+    /// Advances every variable to the next round, and reports for each
+    /// whether it changed.
+    fn changed_each(&mut self) -> Vec<bool> {
+        assert!(
+            self.strata.iter().all(|at_fixpoint| at_fixpoint.get()),
+            "a stratum of this iteration has not reached a fixpoint"
+        );
         self.round += 1;
 
-        let mut result = false;
-        for variable in self.variables.iter_mut() {
-            if variable.changed() {
-                result = true;
+        if !self.recorders.is_empty() {
+            let mut inserts = Vec::new();
+            for recorder in self.recorders.iter() {
+                recorder(&mut inserts);
             }
+            self.trace.push(inserts);
+        }
+
+        let changed: Vec<bool> = self
+            .variables
+            .iter_mut()
+            .map(|variable| variable.changed())
+            .collect();
+        if let Some(at_fixpoint) = &self.at_fixpoint {
+            at_fixpoint.set(!changed.contains(&true));
+        }
+
+        #[cfg(feature = "trace")]
+        {
+            if self.tracing {
+                for variable in self.variables.iter() {
+                    log::trace!(
+                        target: "datafrog",
+                        "round {}: variable {:?} has {} recent and {} stable tuples",
+                        self.round,
+                        variable.name(),
+                        variable.recent_len(),
+                        variable.stable_len(),
+                    );
+                }
+            }
+        }
+
+        changed
+    }
+
+    /// Turns on or off the logging of each variable's progress.
+    ///
+    /// While on, each call to `changed` logs, for each variable, its
+    /// name and its numbers of recent and stable tuples, as `trace`
+    /// level records with the target `datafrog`. Logging is off by
+    /// default.
+    #[cfg(feature = "trace")]
+    pub fn set_trace(&mut self, enabled: bool) {
+        self.tracing = enabled;
+    }
+
+    /// Like `changed`, but returns an error instead of starting another
+    /// round once `max` rounds have been run, so that a computation
+    /// that fails to reach a fixpoint can be detected. The round in which
+    /// `changed` finds the fixpoint counts towards `max`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use datafrog::Iteration;
+    ///
+    /// let mut iteration = Iteration::new();
+    /// let counter = iteration.variable::<u64>();
+    /// counter.extend(vec![0]);
+    ///
+    /// let mut result = Ok(true);
+    /// while let Ok(true) = result {
+    ///     counter.from_map(&counter, |&x| x + 1);
+    ///     result = iteration.changed_with_limit(10);
+    /// }
+    ///
+    /// assert!(result.is_err());
+    /// assert_eq!(iteration.round(), 10);
+    /// ```
+    pub fn changed_with_limit(&mut self, max: usize) -> Result<bool, IterationError> {
+        if self.round() >= max {
+            return Err(IterationError { limit: max });
+        }
+        Ok(self.changed())
+    }
+
+    /// Empties every variable of the iteration, and starts counting
+    /// rounds again, so that the iteration and its variables can be used
+    /// for another computation of the same shape with other facts.
+    ///
+    /// Variables that have been completed have been consumed, and cannot
+    /// be used again; create new ones in their place. If inserts are
+    /// being recorded, recording goes on, but the recorded rounds are
+    /// discarded.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use datafrog::Iteration;
+    ///
+    /// let mut iteration = Iteration::new();
+    /// let numbers = iteration.variable::<u32>();
+    /// let doubled = iteration.variable::<u32>();
+    ///
+    /// for &seed in [1, 5].iter() {
+    ///     iteration.reset();
+    ///     numbers.extend(vec![seed]);
+    ///     while iteration.changed() {
+    ///         doubled.from_map(&numbers, |&x| x * 2);
+    ///     }
+    ///     assert_eq!(doubled.stable_len(), 1);
+    /// }
+    /// ```
+    pub fn reset(&mut self) {
+        for variable in self.variables.iter() {
+            variable.reset();
+        }
+        self.round = 0;
+        self.trace.clear();
+    }
+
+    /// Returns the number of times `changed` has been called, which is
+    /// the number of the round that is underway.
+    pub fn round(&self) -> usize {
+        self.round as usize
+    }
+
+    /// Like `changed`, but afterwards invokes `f` once for each variable
+    /// registered with `observe`, passing the variable's name and the
+    /// tuples that were just promoted to recent (possibly none).
+    pub fn changed_observing_deltas(&mut self, mut f: impl FnMut(&str, &[ErasedTuple])) -> bool {
+        let result = self.changed();
+        for observer in self.observers.iter() {
+            observer(&mut f);
         }
         result
     }
 
-    /// Creates a new named variable associated with the iterative context.
+    /// Starts recording the relations inserted into `variable`.
+    ///
+    /// Each subsequent call to `changed` records a copy of each relation
+    /// inserted into the recorded variables since the previous call, in
+    /// the order the variables were recorded; see `trace`. Inserting the
+    /// recorded relations again, round by round, replays the computation
+    /// of those variables, so that the traces of two runs can be compared
+    /// to find where they diverge.
+    pub fn record<Tuple: Ord + Clone + Any>(&mut self, variable: &Variable<Tuple>) {
+        let variable = variable.clone();
+        self.recorders.push(Box::new(move |inserts| {
+            for relation in variable.to_add.borrow().iter() {
+                inserts.push(RecordedInsert {
+                    variable: variable.name().to_string(),
+                    tuples: relation.len(),
+                    relation: Rc::new(relation.clone()),
+                });
+            }
+        }));
+    }
+
+    /// Returns the inserts recorded since the first call to `record`,
+    /// with one entry per call to `changed`.
+    pub fn trace(&self) -> &[Vec<RecordedInsert>] {
+        &self.trace
+    }
+
+    /// Registers `variable` to have its recent tuples reported by
+    /// `changed_observing_deltas`.
+    pub fn observe<Tuple: Ord + Any>(&mut self, variable: &Variable<Tuple>) {
+        let variable = variable.clone();
+        self.observers.push(Box::new(move |f| {
+            let recent = variable.recent.borrow();
+            let erased: Vec<ErasedTuple> =
+                recent.iter().map(|tuple| tuple as ErasedTuple).collect();
+            f(variable.name(), &erased);
+        }));
+    }
+
+    /// Creates a new variable associated with the iterative context.
     pub fn variable<Tuple: Ord + 'v>(&mut self) -> Variable<Tuple> {
-        let variable = Variable::new();
+        self.variable_named("")
+    }
+
+    /// Creates a new named variable associated with the iterative context.
+    pub fn variable_named<Tuple: Ord + 'v>(&mut self, name: &str) -> Variable<Tuple> {
+        let variable = Variable::new(name, self.config.merge_factor, 0);
         self.variables.push(Box::new(variable.clone()));
         variable
     }
 
+    /// Like `variable_named`, but reserves space for `capacity` tuples
+    /// in the variable's initial storage.
+    ///
+    /// This is only a hint, and does not change the behaviour of the
+    /// variable. The space is reserved once, in the first batch of tuples
+    /// to become recent. That batch then becomes the first stable batch,
+    /// and later batches whose tuples all follow its own are appended to
+    /// it in place, so that a variable expected to grow to about
+    /// `capacity` tuples in order does not reallocate as it does.
+    pub fn variable_with_capacity<Tuple: Ord + 'v>(
+        &mut self,
+        name: &str,
+        capacity: usize,
+    ) -> Variable<Tuple> {
+        let variable = Variable::new(name, self.config.merge_factor, capacity);
+        self.variables.push(Box::new(variable.clone()));
+        variable
+    }
+
+    /// Creates a named variable whose tuples start out as those of
+    /// `snapshot`, taken with `Variable::snapshot`, and already stable.
+    ///
+    /// As the tuples are stable rather than recent, rules do not derive
+    /// their consequences again; only tuples that are new compared to
+    /// the snapshot, and those derived from them, are joined. This
+    /// allows a program that is run again with a few more facts to only
+    /// compute what follows from those, provided that every variable
+    /// they join with is also restored from a snapshot of the same run.
+    pub fn variable_from_snapshot<Tuple: Ord + 'v>(
+        &mut self,
+        name: &str,
+        snapshot: RelationSnapshot<Tuple>,
+    ) -> Variable<Tuple> {
+        let variable = self.variable_named(name);
+        if !snapshot.relation.is_empty() {
+            variable.stable.borrow_mut().push(snapshot.relation);
+        }
+        variable
+    }
+
+    /// Completes `variable`, which belongs to another iterative context,
+    /// and creates a variable of the same name in this context seeded
+    /// with its tuples.
+    ///
+    /// This lets one program build on the results of another, as though
+    /// their variables had been declared together.
+    pub fn import<Tuple: Ord + 'v>(&mut self, variable: Variable<Tuple>) -> Variable<Tuple> {
+        let imported = self.variable_named(variable.name());
+        imported.insert(variable.complete());
+        imported
+    }
+
     /// Creates a new named variable associated with the iterative context.
     ///
     /// This variable will not be maintained distinctly, and may advertise tuples as
     /// recent multiple times (perhaps unboundedly many times).
     pub fn variable_indistinct<Tuple: Ord + 'v>(&mut self) -> Variable<Tuple> {
-        let mut variable = Variable::new();
-        variable.distinct = false;
+        let variable = Variable::new("", self.config.merge_factor, 0);
+        variable.set_distinct(false);
         self.variables.push(Box::new(variable.clone()));
         variable
     }
 }
 
+/// Runs an inner fixpoint computation for each tuple of `outer`, and
+/// returns the union of their results.
+///
+/// For each outer tuple, `inner` is given that tuple and a fresh
+/// `Iteration`, in which it should declare its variables, run them to
+/// a fixpoint, and return the completed result. Each iteration and its
+/// variables are dropped before the next one is created.
+///
+/// # Examples
+///
+/// This example computes the nodes reachable from each of a set of
+/// start nodes, pairing each reachable node with its start node.
+///
+/// ```
+/// use datafrog::{nested_iteration, Relation};
+///
+/// let edges: Relation<(u32, u32)> = vec![(1, 2), (2, 3), (4, 5)].into();
+/// let starts: Relation<u32> = vec![1, 4].into();
+///
+/// let reachable = nested_iteration(&starts, |&start, iteration| {
+///     let reached = iteration.variable::<(u32, u32)>();
+///     reached.insert(vec![(start, start)].into());
+///     while iteration.changed() {
+///         reached.from_join(&reached, &edges, |_, &start, &next| (next, start));
+///     }
+///     reached.complete()
+/// });
+///
+/// assert_eq!(reachable.elements, vec![(1, 1), (2, 1), (3, 1), (4, 4), (5, 4)]);
+/// ```
+pub fn nested_iteration<Outer: Ord, Result: Ord>(
+    outer: &Relation<Outer>,
+    mut inner: impl FnMut(&Outer, &mut Iteration) -> Relation<Result>,
+) -> Relation<Result> {
+    let mut results = Vec::new();
+    for tuple in outer.iter() {
+        let mut iteration = Iteration::new();
+        results.extend(inner(tuple, &mut iteration).elements);
+    }
+    Relation::from_vec(results)
+}
+
+/// Joins `input1` and `input2` once, like `Variable::from_join`, and
+/// adds the results of `logic1` to `out1` and those of `logic2` to
+/// `out2`.
+///
+/// This is useful when the result of a rule feeds two other rules
+/// that need it keyed differently, as the join is not repeated for
+/// each of them.
+pub fn from_join_into_rekeyed<'me, K: Ord, V1: Ord, V2: Ord, R1: Ord, R2: Ord>(
+    input1: &'me Variable<(K, V1)>,
+    input2: impl JoinInput<'me, (K, V2)>,
+    logic1: impl FnMut(&K, &V1, &V2) -> R1,
+    logic2: impl FnMut(&K, &V1, &V2) -> R2,
+    out1: &Variable<R1>,
+    out2: &Variable<R2>,
+) {
+    join::join_into_rekeyed(input1, input2, out1, out2, logic1, logic2)
+}
+
+/// Declares named variables of an `Iteration`, optionally seeding them.
+///
+/// Each variable is bound to a local of the same name, and is given
+/// that name. A seed can be anything that converts into a `Relation`
+/// of the variable's tuples, such as a `Relation` or a `Vec`.
+///
+/// # Examples
+///
+/// ```
+/// use datafrog::{variables, Iteration};
+///
+/// let mut iteration = Iteration::new();
+/// variables!(iteration; edges: (u32, u32) = vec![(1, 2), (2, 3)], reversed: (u32, u32));
+///
+/// while iteration.changed() {
+///     reversed.from_map(&edges, |&(a, b)| (b, a));
+/// }
+///
+/// assert_eq!(edges.name(), "edges");
+/// assert_eq!(reversed.complete().elements, vec![(2, 1), (3, 2)]);
+/// ```
+#[macro_export]
+macro_rules! variables {
+    ($iteration:expr; $($name:ident : $tuple:ty $(= $seed:expr)?),* $(,)?) => {
+        $(
+            let $name = $iteration.variable_named::<$tuple>(stringify!($name));
+            $($name.insert(::core::convert::Into::into($seed));)?
+        )*
+    };
+}
+
+/// Declares the variables of an `Iteration` and runs rules over them
+/// to a fixpoint.
+///
+/// The variables are declared as by `variables!`, and remain in scope
+/// afterwards, so that they can be completed. Each rule is one of
+///
+/// - a map, `out(x, y) <- input(a, b);`, which becomes
+///   `out.from_map(&input, |&(a, b)| (x, y))`, for atoms of any arity;
+/// - a join, `out(a, c) <- input1(a, b), input2(b, c);`, which joins
+///   the two atoms on the argument they have in common.
+///
+/// In a join, both atoms must have two arguments, each of which is a
+/// variable, `_`, or a parenthesized tuple of them, and exactly one of
+/// the arguments of the first atom must also appear in the second; it
+/// is a compile error if none does. A join becomes a `from_join`, and
+/// an atom whose common argument comes second is first swapped into a
+/// new variable by a `from_map`.
+///
+/// The arguments of an atom are patterns, and the values they bind must
+/// be `Copy`. The head of a rule is an expression built from them.
+///
+/// # Examples
+///
+/// ```
+/// use datafrog::{datalog, Iteration};
+///
+/// let mut iteration = Iteration::new();
+/// datalog! {
+///     iteration;
+///     variables {
+///         edges: (u32, u32) = vec![(1, 2), (2, 3), (3, 4)],
+///         reach: (u32, u32),
+///     }
+///     rules {
+///         reach(a, b) <- edges(a, b);
+///         reach(a, c) <- reach(a, b), edges(b, c);
+///     }
+/// }
+///
+/// assert_eq!(reach.complete().len(), 6);
+/// ```
+#[macro_export]
+macro_rules! datalog {
+    ($iteration:expr; variables { $($variables:tt)* } rules { $($rules:tt)* }) => {
+        $crate::variables!($iteration; $($variables)*);
+        $crate::datalog!(@rules $iteration; {} {} $($rules)*);
+    };
+    // Every rule has been translated, into the variables it needs and
+    // the statements that apply it.
+    (@rules $iteration:expr; { $($setup:tt)* } { $($body:tt)* }) => {
+        $($setup)*
+        while $iteration.changed() {
+            $($body)*
+        }
+    };
+    // A join. The arguments of the atoms are compared by a local macro
+    // whose rules are built from those of the second atom; `_` is never
+    // a common argument.
+    (@rules $iteration:expr; { $($setup:tt)* } { $($body:tt)* }
+        $out:ident ( $($head:tt)* ) <-
+            $input1:ident ( $x1:tt , $y1:tt ) ,
+            $input2:ident ( $x2:tt , $y2:tt ) ;
+        $($rest:tt)*
+    ) => {{
+        macro_rules! datalog_join {
+            (_ _) => { datalog_join!() };
+            (_ $x2) => { datalog_join!(swap, keep) };
+            (_ $y2) => { datalog_join!(swap, swap) };
+            ($x2 _) => { datalog_join!(keep, keep) };
+            ($y2 _) => { datalog_join!(keep, swap) };
+            (_ $y1) => { datalog_join!() };
+            ($x1 _) => { datalog_join!() };
+            ($x2 $y1) => { datalog_join!(keep, keep) };
+            ($y2 $y1) => { datalog_join!(keep, swap) };
+            ($x1 $x2) => { datalog_join!(swap, keep) };
+            ($x1 $y2) => { datalog_join!(swap, swap) };
+            ($x1 $y1) => { datalog_join!() };
+            () => {
+                compile_error!(concat!(
+                    "the atoms `", stringify!($input1($x1, $y1)), "` and `",
+                    stringify!($input2($x2, $y2)), "` have no argument in common",
+                ));
+            };
+            (keep, keep) => {
+                $crate::datalog!(@rules $iteration; { $($setup)* } {
+                    $($body)*
+                    #[allow(unused_variables)]
+                    $out.from_join(&$input1, &$input2, |&$x1, &$y1, &$y2| ($($head)*));
+                } $($rest)*);
+            };
+            (swap, keep) => {
+                $crate::datalog!(@rules $iteration; {
+                    $($setup)*
+                    let swapped1 = $iteration.variable();
+                } {
+                    $($body)*
+                    swapped1.from_map(&$input1, |&(x, y)| (y, x));
+                    #[allow(unused_variables)]
+                    $out.from_join(&swapped1, &$input2, |&$y1, &$x1, &$y2| ($($head)*));
+                } $($rest)*);
+            };
+            (keep, swap) => {
+                $crate::datalog!(@rules $iteration; {
+                    $($setup)*
+                    let swapped2 = $iteration.variable();
+                } {
+                    $($body)*
+                    swapped2.from_map(&$input2, |&(x, y)| (y, x));
+                    #[allow(unused_variables)]
+                    $out.from_join(&$input1, &swapped2, |&$x1, &$y1, &$x2| ($($head)*));
+                } $($rest)*);
+            };
+            (swap, swap) => {
+                $crate::datalog!(@rules $iteration; {
+                    $($setup)*
+                    let swapped1 = $iteration.variable();
+                    let swapped2 = $iteration.variable();
+                } {
+                    $($body)*
+                    swapped1.from_map(&$input1, |&(x, y)| (y, x));
+                    swapped2.from_map(&$input2, |&(x, y)| (y, x));
+                    #[allow(unused_variables)]
+                    $out.from_join(&swapped1, &swapped2, |&$y1, &$x1, &$x2| ($($head)*));
+                } $($rest)*);
+            };
+        }
+        datalog_join!($x1 $y1);
+    }};
+    (@rules $iteration:expr; { $($setup:tt)* } { $($body:tt)* }
+        $out:ident ( $($head:tt)* ) <- $input:ident ( $($args:tt)* ) ;
+        $($rest:tt)*
+    ) => {
+        $crate::datalog!(@rules $iteration; { $($setup)* } {
+            $($body)*
+            $out.from_map(&$input, |&($($args)*)| ($($head)*));
+        } $($rest)*);
+    };
+}
+
+/// Creates a closure that projects a struct onto some of its fields,
+/// for use as the key of a join, such as by `Variable::from_join_by_key`.
+///
+/// `key!(Type, field)` creates a closure that takes a `&Type` and returns
+/// a clone of its `field`, while `key!(Type, field1, field2, ...)`
+/// returns a tuple of clones of the named fields, in order.
+///
+/// # Examples
+///
+/// ```
+/// use datafrog::{key, Relation};
+///
+/// #[derive(Clone, PartialEq, Eq, PartialOrd, Ord)]
+/// struct Access {
+///     function: u32,
+///     variable: u32,
+///     line: u32,
+/// }
+///
+/// let accesses: Relation<Access> = vec![
+///     Access { function: 1, variable: 7, line: 10 },
+///     Access { function: 1, variable: 8, line: 12 },
+/// ]
+/// .into();
+///
+/// let lines = Relation::from_map(&accesses, |a| (key!(Access, function, variable)(a), a.line));
+/// assert_eq!(lines.elements, vec![((1, 7), 10), ((1, 8), 12)]);
+/// ```
+#[macro_export]
+macro_rules! key {
+    ($type:ty, $field:ident $(,)?) => {
+        |tuple: &$type| ::core::clone::Clone::clone(&tuple.$field)
+    };
+    ($type:ty, $($field:ident),+ $(,)?) => {
+        |tuple: &$type| ($(::core::clone::Clone::clone(&tuple.$field),)+)
+    };
+}
+
 /// A type that can report on whether it has changed.
 trait VariableTrait {
+    /// Returns the name of the variable.
+    fn name(&self) -> &str;
+
     /// Reports whether the variable has changed since it was last asked.
     fn changed(&mut self) -> bool;
+
+    /// Returns the number of recent tuples.
+    fn recent_len(&self) -> usize;
+
+    /// Returns the number of stable tuples.
+    #[cfg(feature = "trace")]
+    fn stable_len(&self) -> usize;
+
+    /// Removes all tuples, stable, recent and yet to be added.
+    fn reset(&self);
 }
 
 /// An monotonically increasing set of `Tuple`s.
@@ -281,16 +1863,28 @@ trait VariableTrait {
 /// and it is important that any cycle of derivations have at least one de-duplicating
 /// variable on it.
 pub struct Variable<Tuple: Ord> {
-    /// Should the variable be maintained distinctly.
-    distinct: bool,
+    /// Identifying name for the variable; empty if it was not given one.
+    name: String,
+    /// Should the variable be maintained distinctly; see `set_distinct`.
+    distinct: Rc<Cell<bool>>,
+    /// How eagerly stable batches are merged; see `IterationConfig`.
+    merge_factor: usize,
+    /// Tuples to reserve space for in the first batches; see
+    /// `Iteration::variable_with_capacity`.
+    capacity: usize,
     /// A list of relations whose union are the accepted tuples.
     pub stable: Rc<RefCell<Vec<Relation<Tuple>>>>,
     /// A list of recent tuples, still to be processed.
     pub recent: Rc<RefCell<Relation<Tuple>>>,
     /// A list of future tuples, to be introduced.
     to_add: Rc<RefCell<Vec<Relation<Tuple>>>>,
+    /// Compares tuples by a projection, if set by `dedup_projection`.
+    projection: Rc<RefCell<Option<ProjectionCmp<Tuple>>>>,
 }
 
+/// Compares two tuples by their projections.
+type ProjectionCmp<Tuple> = Box<dyn Fn(&Tuple, &Tuple) -> Ordering>;
+
 // Operator implementations.
 impl<Tuple: Ord> Variable<Tuple> {
     /// Adds tuples that result from joining `input1` and `input2` --
@@ -319,37 +1913,243 @@ impl<Tuple: Ord> Variable<Tuple> {
     /// ```
     /// use datafrog::{Iteration, Relation};
     ///
-    /// let mut iteration = Iteration::new();
-    /// let variable = iteration.variable::<(usize, usize)>("source");
-    /// variable.extend((0 .. 10).map(|x| (x, x + 1)));
-    /// variable.extend((0 .. 10).map(|x| (x + 1, x)));
+    /// let mut iteration = Iteration::new();
+    /// let variable = iteration.variable::<(usize, usize)>();
+    /// variable.extend((0 .. 10).map(|x| (x, x + 1)));
+    /// variable.extend((0 .. 10).map(|x| (x + 1, x)));
+    ///
+    /// while iteration.changed() {
+    ///     variable.from_join(&variable, &variable, |&key, &val1, &val2| (val1, val2));
+    /// }
+    ///
+    /// let result = variable.complete();
+    /// assert_eq!(result.len(), 121);
+    /// ```
+    pub fn from_join<'me, K: Ord, V1: Ord, V2: Ord>(
+        &self,
+        input1: &'me Variable<(K, V1)>,
+        input2: impl JoinInput<'me, (K, V2)>,
+        mut logic: impl FnMut(&K, &V1, &V2) -> Tuple,
+    ) {
+        self.from_join_filtered(input1, input2, |k, v1, v2| Some(logic(k, v1, v2)))
+    }
+
+    /// Like `from_join_adv`, but the selected keys may be any part of
+    /// the tuples, such as a trailing field, rather than one ordered
+    /// consistently with them.
+    ///
+    /// As the tuples are not sorted by these keys, each pair of batches
+    /// to be joined is sorted by key first, which `from_join_adv` does
+    /// not need to do.
+    pub fn from_join_by<'me, K: Ord, T1: Ord, T2: Ord>(
+        &self,
+        input1: &'me Variable<T1>,
+        input2: impl JoinInput<'me, T2>,
+        key1: impl Fn(&T1) -> &K,
+        key2: impl Fn(&T2) -> &K,
+        logic: impl FnMut(&K, &T1, &T2) -> Tuple,
+    ) {
+        join::join_into_by(input1, input2, self, key1, key2, logic)
+    }
+
+    /// Like `from_join_by`, but the keys are computed from the tuples
+    /// rather than borrowed from them, so that they may combine several
+    /// fields, as the closures created by `key!` do.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use datafrog::{key, Iteration, Relation};
+    ///
+    /// #[derive(Clone, PartialEq, Eq, PartialOrd, Ord)]
+    /// struct Edge {
+    ///     from: u32,
+    ///     to: u32,
+    ///     label: char,
+    /// }
+    ///
+    /// let mut iteration = Iteration::new();
+    /// let edges = iteration.variable::<Edge>();
+    /// let reversed = iteration.variable::<(char, char)>();
+    /// edges.extend(vec![
+    ///     Edge { from: 1, to: 2, label: 'a' },
+    ///     Edge { from: 2, to: 1, label: 'b' },
+    /// ]);
+    ///
+    /// while iteration.changed() {
+    ///     reversed.from_join_by_key(
+    ///         &edges,
+    ///         &edges,
+    ///         key!(Edge, from, to),
+    ///         key!(Edge, to, from),
+    ///         |_, e1, e2| (e1.label, e2.label),
+    ///     );
+    /// }
+    ///
+    /// assert_eq!(reversed.complete().elements, vec![('a', 'b'), ('b', 'a')]);
+    /// ```
+    pub fn from_join_by_key<'me, K: Ord, T1: Ord, T2: Ord>(
+        &self,
+        input1: &'me Variable<T1>,
+        input2: impl JoinInput<'me, T2>,
+        key1: impl Fn(&T1) -> K,
+        key2: impl Fn(&T2) -> K,
+        logic: impl FnMut(&K, &T1, &T2) -> Tuple,
+    ) {
+        join::join_into_by_key(input1, input2, self, key1, key2, logic)
+    }
+
+    /// Like `from_join`, but removes duplicate results while joining,
+    /// rather than only once all have been produced.
+    ///
+    /// This bounds the memory needed by joins that produce many copies
+    /// of few distinct tuples, such as in dense graphs, at the cost of
+    /// sorting the results repeatedly. Joins whose results are mostly
+    /// distinct are faster with `from_join`.
+    pub fn from_join_dedup<'me, K: Ord, V1: Ord, V2: Ord>(
+        &self,
+        input1: &'me Variable<(K, V1)>,
+        input2: impl JoinInput<'me, (K, V2)>,
+        logic: impl FnMut(&K, &V1, &V2) -> Tuple,
+    ) {
+        join::join_into_dedup(input1, input2, self, logic)
+    }
+
+    /// Like `from_join`, but invokes `logic` only once for each key
+    /// the inputs have in common, with the first values for that key,
+    /// rather than once for each pair of values.
     ///
-    /// while iteration.changed() {
-    ///     variable.from_join(&variable, &variable, |&key, &val1, &val2| (val1, val2));
-    /// }
+    /// This is for rules that only need to know that a match exists,
+    /// where keys with many values on both sides would otherwise produce
+    /// a large cross-product only to collapse it again. Each key invokes
+    /// `logic` once overall, in the round in which it first matches, and
+    /// not again as more values for it arrive in later rounds.
+    pub fn from_join_exists<'me, K: Ord, V1: Ord, V2: Ord>(
+        &self,
+        input1: &'me Variable<(K, V1)>,
+        input2: impl JoinInput<'me, (K, V2)>,
+        logic: impl FnMut(&K, &V1, &V2) -> Tuple,
+    ) {
+        join::join_into_exists(input1, input2, self, logic)
+    }
+
+    /// Adds tuples that result from joining three inputs on a common
+    /// key, without materializing the join of the first two.
     ///
-    /// let result = variable.complete();
-    /// assert_eq!(result.len(), 121);
-    /// ```
-    pub fn from_join<'me, K: Ord, V1: Ord, V2: Ord>(
+    /// As with `from_join`, the first input must be a variable, while
+    /// the others can be variables or relations.
+    pub fn from_join3<'me, K: Ord, V1: Ord, V2: Ord, V3: Ord>(
         &self,
         input1: &'me Variable<(K, V1)>,
         input2: impl JoinInput<'me, (K, V2)>,
-        mut logic: impl FnMut(&K, &V1, &V2) -> Tuple,
+        input3: impl JoinInput<'me, (K, V3)>,
+        logic: impl FnMut(&K, &V1, &V2, &V3) -> Tuple,
     ) {
-        join::join_into(input1, input2, self, |(k, _)| k, |(k, _)| k, |k, v1, v2| logic(k, &v1.1, &v2.1))
+        join::join_into3(input1, input2, input3, self, logic)
+    }
+
+    /// Like `from_join`, but only adds the tuples for which `logic`
+    /// returns `Some`. This avoids constructing tuples that would
+    /// immediately be discarded.
+    pub fn from_join_filtered<'me, K: Ord, V1: Ord, V2: Ord>(
+        &self,
+        input1: &'me Variable<(K, V1)>,
+        input2: impl JoinInput<'me, (K, V2)>,
+        mut logic: impl FnMut(&K, &V1, &V2) -> Option<Tuple>,
+    ) {
+        join::join_into(
+            input1,
+            input2,
+            self,
+            |(k, _)| k,
+            |(k, _)| k,
+            |k, v1, v2| logic(k, &v1.1, &v2.1),
+        )
     }
 
     /// Like `from_join`, but lets the caller choose how keys are selected.
+    ///
+    /// The selected keys must be ordered consistently with the tuples,
+    /// for example by selecting a leading field. The join only ever
+    /// compares the selected keys, never the whole tuples, so for wide
+    /// tuples this is cheaper than first mapping the inputs into
+    /// `(key, tuple)` pairs.
+    ///
+    /// Keys may be composite: for tuples of the form `((a, b), c)`,
+    /// selecting `|(ab, _)| ab` joins on the pair `(a, b)` without
+    /// repacking the tuples.
     pub fn from_join_adv<'me, K: Ord, T1: Ord, T2: Ord>(
         &self,
         input1: &'me Variable<T1>,
         input2: impl JoinInput<'me, T2>,
         input1_key: impl Fn(&T1) -> &K,
         input2_key: impl Fn(&T2) -> &K,
-        logic: impl FnMut(&K, &T1, &T2) -> Tuple,
+        mut logic: impl FnMut(&K, &T1, &T2) -> Tuple,
+    ) {
+        join::join_into(input1, input2, self, input1_key, input2_key, |k, v1, v2| {
+            Some(logic(k, v1, v2))
+        })
+    }
+
+    /// Like `from_join`, but for inputs whose keys have different types.
+    /// The keys of each input are converted to a common key type with
+    /// `to_common1` and `to_common2`, and tuples are joined when their
+    /// converted keys are equal.
+    ///
+    /// The conversions must preserve the order of the keys, as the
+    /// inputs are walked in their sorted order; widening integer
+    /// conversions such as `u32` to `u64` do.
+    pub fn from_join_convert_key<'me, K1: Ord, V1: Ord, K2: Ord, V2: Ord, K: Ord>(
+        &self,
+        input1: &'me Variable<(K1, V1)>,
+        input2: impl JoinInput<'me, (K2, V2)>,
+        to_common1: impl Fn(&K1) -> K,
+        to_common2: impl Fn(&K2) -> K,
+        logic: impl FnMut(&K, &V1, &V2) -> Tuple,
+    ) {
+        join::join_into_converted(input1, input2, self, to_common1, to_common2, logic)
+    }
+
+    /// Adds tuples that result from combining the values that `input1`
+    /// and `input2` have for each key.
+    ///
+    /// This is an inner join, like `from_join`, for inputs that have at
+    /// most one value per key, so that each key produces at most one
+    /// tuple. That requirement is checked in debug builds.
+    pub fn from_binary_map<'me, K: Ord, V1: Ord, V2: Ord>(
+        &self,
+        input1: &'me Variable<(K, V1)>,
+        input2: &'me Variable<(K, V2)>,
+        logic: impl FnMut(&K, &V1, &V2) -> Tuple,
+    ) {
+        join::join_into_one_to_one(input1, input2, self, logic)
+    }
+
+    /// Adds the result of `logic` for each pair of a tuple from `input1`
+    /// and a tuple from `input2`, with no key in common.
+    ///
+    /// Like joins, each round only combines the pairs in which at least
+    /// one tuple is recent. The number of pairs is the product of the
+    /// sizes of the inputs, so this is only suitable for small inputs.
+    pub fn from_product<'me, T1: Ord, T2: Ord>(
+        &self,
+        input1: &'me Variable<T1>,
+        input2: impl JoinInput<'me, T2>,
+        logic: impl FnMut(&T1, &T2) -> Tuple,
+    ) {
+        join::product_into(input1, input2, self, logic)
+    }
+
+    /// Like `from_join`, but joins against an `IndexedRelation`, looking
+    /// up the tuples for each key in its index rather than searching the
+    /// relation for them.
+    pub fn from_join_indexed<K: Ord, V1: Ord, V2: Ord>(
+        &self,
+        input1: &Variable<(K, V1)>,
+        input2: &IndexedRelation<K, V2>,
+        logic: impl FnMut(&K, &V1, &V2) -> Tuple,
     ) {
-        join::join_into(input1, input2, self, input1_key, input2_key, logic)
+        join::join_into_indexed(input1, input2, self, logic)
     }
 
     /// Adds tuples from `input1` whose key is not present in `input2`.
@@ -369,7 +2169,7 @@ impl<Tuple: Ord> Variable<Tuple> {
     /// use datafrog::{Iteration, Relation};
     ///
     /// let mut iteration = Iteration::new();
-    /// let variable = iteration.variable::<(usize, usize)>("source");
+    /// let variable = iteration.variable::<(usize, usize)>();
     /// variable.extend((0 .. 10).map(|x| (x, x + 1)));
     ///
     /// let relation: Relation<_> = (0 .. 10).filter(|x| x % 3 == 0).collect();
@@ -387,7 +2187,150 @@ impl<Tuple: Ord> Variable<Tuple> {
         input2: &Relation<K>,
         logic: impl FnMut(&K, &V) -> Tuple,
     ) {
-        self.insert(join::antijoin(input1, input2, logic))
+        self.insert(join::antijoin(&input1.recent.borrow(), input2, logic))
+    }
+
+    /// Inserts the tuples of `input` whose key is absent from `anti`,
+    /// after transforming them with `logic`.
+    ///
+    /// This is the same as inserting `Relation::from_antijoin(input,
+    /// anti, logic)`, and is meant for seeding a variable from two
+    /// relations before the iteration starts, where `from_antijoin`
+    /// needs the first input to be a variable.
+    pub fn insert_antijoin<K: Ord, V: Ord>(
+        &self,
+        input: &Relation<(K, V)>,
+        anti: &Relation<K>,
+        logic: impl FnMut(&K, &V) -> Tuple,
+    ) {
+        self.insert(join::antijoin(input, anti, logic))
+    }
+
+    /// Adds tuples from `input` whose key is present in `filter`, after
+    /// transforming them with `logic`. This is the complement of
+    /// `from_antijoin`, and like it, the result will not vary during the
+    /// iteration if `input` does not.
+    ///
+    /// # Examples
+    ///
+    /// This example starts a collection with the pairs (x, x+1) for x in 0 .. 10. It then
+    /// adds any pairs (x+1,x) for which x is a multiple of three. That includes four
+    /// pairs (for 0, 3, 6, and 9) which should leave us with 14 total pairs.
+    ///
+    /// ```
+    /// use datafrog::{Iteration, Relation};
+    ///
+    /// let mut iteration = Iteration::new();
+    /// let variable = iteration.variable::<(usize, usize)>();
+    /// variable.extend((0 .. 10).map(|x| (x, x + 1)));
+    ///
+    /// let relation: Relation<_> = (0 .. 10).filter(|x| x % 3 == 0).collect();
+    ///
+    /// while iteration.changed() {
+    ///     variable.from_semijoin(&variable, &relation, |&key, &val| (val, key));
+    /// }
+    ///
+    /// let result = variable.complete();
+    /// assert_eq!(result.len(), 14);
+    /// ```
+    pub fn from_semijoin<K: Ord, V: Ord>(
+        &self,
+        input: &Variable<(K, V)>,
+        filter: &Relation<K>,
+        logic: impl FnMut(&K, &V) -> Tuple,
+    ) {
+        self.insert(join::semijoin(input, filter, logic))
+    }
+
+    /// Like `from_join`, but for a second input held in a slice rather
+    /// than a relation. The slice must be sorted and must not contain
+    /// duplicates, as a relation would be; this is not checked.
+    pub fn from_join_slice<K: Ord, V1: Ord, V2: Ord>(
+        &self,
+        input1: &Variable<(K, V1)>,
+        input2: &[(K, V2)],
+        logic: impl FnMut(&K, &V1, &V2) -> Tuple,
+    ) {
+        join::join_into_slice(input1, input2, self, logic)
+    }
+
+    /// Like `from_antijoin`, but for keys held in a slice rather than a
+    /// relation. The slice must be sorted and must not contain
+    /// duplicates, as a relation would be; this is not checked.
+    pub fn from_antijoin_slice<K: Ord, V: Ord>(
+        &self,
+        input1: &Variable<(K, V)>,
+        input2: &[K],
+        logic: impl FnMut(&K, &V) -> Tuple,
+    ) {
+        self.insert(join::antijoin(&input1.recent.borrow(), input2, logic))
+    }
+
+    /// Adds the recent tuples of `input` whose key, as computed by
+    /// `key_fn`, is not present in `relation`.
+    ///
+    /// This is like mapping `input` into `(key, tuple)` pairs and
+    /// antijoining them with `relation`, but without the intermediate
+    /// variable. `relation` is searched by galloping forward from the
+    /// previous key, and from its start whenever a key is less than the
+    /// one before it, so this is fastest for keys ordered consistently
+    /// with the tuples, as a projection onto leading fields is.
+    pub fn filter_anti<K: Ord>(
+        &self,
+        input: &Variable<Tuple>,
+        relation: &Relation<K>,
+        key_fn: impl Fn(&Tuple) -> K,
+    ) where
+        Tuple: Clone,
+    {
+        join::filter_anti_into(input, relation, self, key_fn)
+    }
+
+    /// Like `from_antijoin`, but lets the caller choose which part of
+    /// each tuple of `input` is looked up in `anti`, so that the key
+    /// need not be the leading field.
+    pub fn from_antijoin_by<K: Ord, T: Ord>(
+        &self,
+        input: &Variable<T>,
+        anti: &Relation<K>,
+        key_fn: impl Fn(&T) -> &K,
+        logic: impl FnMut(&T) -> Tuple,
+    ) {
+        self.insert(join::antijoin_by(input, anti, key_fn, logic))
+    }
+
+    /// Adds the tuples that result from joining this variable with
+    /// itself, where `edge_key` selects the source and target of each
+    /// tuple, and `logic` combines a tuple `(a, b)` with a tuple
+    /// `(b, c)` into a tuple `(a, c)`.
+    ///
+    /// Calling this once per round computes the transitive closure of
+    /// the variable. The tuples must be ordered by their source first;
+    /// as they are not ordered by their target, each batch of tuples is
+    /// re-sorted by target whenever it is joined.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use datafrog::Iteration;
+    ///
+    /// let mut iteration = Iteration::new();
+    /// let variable = iteration.variable::<(usize, usize)>();
+    /// variable.extend((0 .. 10).map(|x| (x, x + 1)));
+    ///
+    /// while iteration.changed() {
+    ///     variable.transitively_close(|(a, b)| (a, b), |&(a, _), &(_, c)| (a, c));
+    /// }
+    ///
+    /// let result = variable.complete();
+    /// assert_eq!(result.len(), 55);
+    /// ```
+    pub fn transitively_close<K: Ord>(
+        &self,
+        edge_key: impl Fn(&Tuple) -> (&K, &K),
+        logic: impl FnMut(&Tuple, &Tuple) -> Tuple,
+    ) {
+        join::join_into_transitive(self, edge_key, logic)
     }
 
     /// Adds tuples that result from mapping `input`.
@@ -403,7 +2346,7 @@ impl<Tuple: Ord> Variable<Tuple> {
     /// use datafrog::{Iteration, Relation};
     ///
     /// let mut iteration = Iteration::new();
-    /// let variable = iteration.variable::<(usize, usize)>("source");
+    /// let variable = iteration.variable::<(usize, usize)>();
     /// variable.extend((0 .. 10).map(|x| (x, x)));
     ///
     /// while iteration.changed() {
@@ -423,6 +2366,108 @@ impl<Tuple: Ord> Variable<Tuple> {
         map::map_into(input, self, logic)
     }
 
+    /// Like `from_map`, but only adds the tuples for which `logic`
+    /// returns `Some`.
+    pub fn from_map_filtered<T2: Ord>(
+        &self,
+        input: &Variable<T2>,
+        logic: impl FnMut(&T2) -> Option<Tuple>,
+    ) {
+        map::map_filter_into(input, self, logic)
+    }
+
+    /// Like `from_map`, but also gives `logic` the value that `table`
+    /// pairs with the key of each tuple, as selected by `key_fn`, or
+    /// `None` if the key is absent from the table.
+    ///
+    /// This treats `table` as a function from keys to values, such as
+    /// attributes to enrich tuples with, and produces a result for every
+    /// tuple whether or not its key is found. If the table has several
+    /// values for a key, the least is given.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use datafrog::{Iteration, Relation};
+    ///
+    /// let names: Relation<(u32, &str)> = vec![(1, "one"), (2, "two")].into();
+    ///
+    /// let mut iteration = Iteration::new();
+    /// let numbers = iteration.variable::<u32>();
+    /// let named = iteration.variable::<(u32, &str)>();
+    /// numbers.extend(vec![1, 2, 3]);
+    ///
+    /// while iteration.changed() {
+    ///     named.from_map_lookup(&numbers, &names, |n| n, |&n, name| {
+    ///         (n, name.copied().unwrap_or("many"))
+    ///     });
+    /// }
+    ///
+    /// assert_eq!(named.complete().elements, vec![(1, "one"), (2, "two"), (3, "many")]);
+    /// ```
+    pub fn from_map_lookup<T2: Ord, K: Ord, V: Ord>(
+        &self,
+        input: &Variable<T2>,
+        table: &Relation<(K, V)>,
+        key_fn: impl Fn(&T2) -> &K,
+        logic: impl FnMut(&T2, Option<&V>) -> Tuple,
+    ) {
+        map::map_lookup_into(input, self, table, key_fn, logic)
+    }
+
+    /// Adds the tuples that result from applying `step` to the recent
+    /// tuples of this variable, ignoring those for which it returns
+    /// `None`.
+    ///
+    /// Calling this once per round closes the variable under `step`:
+    /// when the iteration completes, the variable contains the result of
+    /// applying `step` to each of its tuples.
+    ///
+    /// # Examples
+    ///
+    /// This example computes the same Collatz journeys as the example of
+    /// `from_map`, but stops each journey once it reaches 1.
+    ///
+    /// ```
+    /// use datafrog::Iteration;
+    ///
+    /// let mut iteration = Iteration::new();
+    /// let variable = iteration.variable::<(usize, usize)>();
+    /// variable.extend((1 .. 10).map(|x| (x, x)));
+    ///
+    /// while iteration.changed() {
+    ///     variable.close_under(|&(key, val)| match val {
+    ///         1 => None,
+    ///         _ if val % 2 == 0 => Some((key, val / 2)),
+    ///         _ => Some((key, 3 * val + 1)),
+    ///     });
+    /// }
+    ///
+    /// let result = variable.complete();
+    /// assert_eq!(result.len(), 70);
+    /// ```
+    pub fn close_under(&self, step: impl FnMut(&Tuple) -> Option<Tuple>) {
+        map::map_filter_into(self, self, step)
+    }
+
+    /// Adds tuples that result from expanding each key of `input` into
+    /// the relation returned by `expand`, and then applying `logic` to
+    /// each key and expanded value.
+    ///
+    /// This is like joining `input` against a lookup relation, except
+    /// that the values for each key are computed on demand rather than
+    /// stored. As with `from_map`, only the recent tuples of `input`
+    /// are expanded, so `expand` should always return the same relation
+    /// for the same key.
+    pub fn from_map_expand<K: Ord, V: Ord>(
+        &self,
+        input: &Variable<K>,
+        expand: impl Fn(&K) -> Relation<V>,
+        logic: impl FnMut(&K, &V) -> Tuple,
+    ) {
+        map::map_expand_into(input, self, expand, logic)
+    }
+
     /// Adds tuples that result from combining `source` with the
     /// relations given in `leapers`. This operation is very flexible
     /// and can be used to do a combination of joins and anti-joins.
@@ -461,27 +2506,186 @@ impl<Tuple: Ord> Variable<Tuple> {
     }
 }
 
+impl<K: Ord + Clone, V1: Ord + Clone, V2: Ord + Clone> Variable<(K, V1, V2)> {
+    /// Adds the natural join of `input1` and `input2`: a tuple
+    /// `(key, v1, v2)` for each pair of tuples `(key, v1)` and
+    /// `(key, v2)`. This is `from_join` with a closure that clones the
+    /// key and values into the output.
+    pub fn from_join_tuple<'me>(
+        &self,
+        input1: &'me Variable<(K, V1)>,
+        input2: impl JoinInput<'me, (K, V2)>,
+    ) {
+        self.from_join(input1, input2, |k, v1, v2| {
+            (k.clone(), v1.clone(), v2.clone())
+        })
+    }
+}
+
+impl<K: Ord, V1: Ord> Variable<(K, V1)> {
+    /// Joins this variable with `other`, and invokes `f` for each triple
+    /// that `from_join` would pass to its `logic` in this round, without
+    /// adding anything to a variable.
+    ///
+    /// This is for streaming the results of a join, such as to a file,
+    /// where they are not needed by any rule; as nothing is added, it
+    /// takes no part in reaching a fixpoint. Over the rounds of an
+    /// iteration, each pair of matching tuples is visited once.
+    pub fn for_each_join<'me, V2: Ord>(
+        &self,
+        other: impl JoinInput<'me, (K, V2)>,
+        f: impl FnMut(&K, &V1, &V2),
+    ) {
+        join::for_each_join(self, other, f)
+    }
+
+    /// Joins the recent tuples of this variable with `other` `depth`
+    /// times in succession, each time joining the results of the last,
+    /// and adds all of the results to the variable at once.
+    ///
+    /// This computes the first `depth` rounds of the recursive rule
+    /// `self.from_join(self, other, logic)` without the bookkeeping of
+    /// running each round through `Iteration::changed`. Continuing to
+    /// apply that rule afterwards completes the fixpoint as usual; the
+    /// results of the unrolled rounds are rediscovered, but add
+    /// nothing new. This pays off for shallow recursions, where the
+    /// unrolled rounds are most or all of the work.
+    pub fn unroll_join<V2: Ord>(
+        &self,
+        other: &Relation<(K, V2)>,
+        depth: usize,
+        mut logic: impl FnMut(&K, &V1, &V2) -> (K, V1),
+    ) {
+        let mut results = Vec::new();
+        let mut frontier = {
+            let recent = self.recent.borrow();
+            join::join_into_relation(
+                &recent,
+                other,
+                |(k, _)| k,
+                |(k, _)| k,
+                |k, v1, v2| logic(k, &v1.1, &v2.1),
+            )
+        };
+        for _ in 1..depth {
+            let next = join::join_into_relation(
+                &frontier,
+                other,
+                |(k, _)| k,
+                |(k, _)| k,
+                |k, v1, v2| logic(k, &v1.1, &v2.1),
+            );
+            results.push(frontier);
+            frontier = next;
+        }
+        if depth > 0 {
+            results.push(frontier);
+        }
+
+        for relation in results {
+            self.insert(relation);
+        }
+    }
+
+    /// Returns the tuples that `output.from_join(self, other, logic)`
+    /// would add to `output` in the current round, without adding them.
+    ///
+    /// This lets a test check the outcome of a single step of a join,
+    /// without running the whole computation to a fixpoint.
+    pub fn join_step_debug<'me, V2: Ord, Result: Ord>(
+        &self,
+        other: impl JoinInput<'me, (K, V2)>,
+        mut logic: impl FnMut(&K, &V1, &V2) -> Result,
+    ) -> Relation<Result> {
+        join::join_step(
+            self,
+            other,
+            |(k, _)| k,
+            |(k, _)| k,
+            |k, v1, v2| Some(logic(k, &v1.1, &v2.1)),
+        )
+    }
+}
+
 impl<Tuple: Ord> Clone for Variable<Tuple> {
     fn clone(&self) -> Self {
         Variable {
-            distinct: self.distinct,
+            name: self.name.clone(),
+            distinct: self.distinct.clone(),
+            merge_factor: self.merge_factor,
+            capacity: self.capacity,
             stable: self.stable.clone(),
             recent: self.recent.clone(),
             to_add: self.to_add.clone(),
+            projection: self.projection.clone(),
         }
     }
 }
 
 impl<Tuple: Ord> Variable<Tuple> {
-    fn new() -> Self {
+    fn new(name: &str, merge_factor: usize, capacity: usize) -> Self {
         Variable {
-            distinct: true,
+            name: name.to_string(),
+            distinct: Rc::new(Cell::new(true)),
+            merge_factor,
+            capacity,
             stable: Rc::new(RefCell::new(Vec::new())),
             recent: Rc::new(RefCell::new(Vec::new().into())),
             to_add: Rc::new(RefCell::new(Vec::new())),
+            projection: Rc::new(RefCell::new(None)),
         }
     }
 
+    /// Returns the name given to the variable, or an empty string if
+    /// it was created without one.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Treats tuples with equal projections under `proj` as the same
+    /// fact: from now on, a tuple is only added to the variable if no
+    /// tuple with the same projection has been added before, and only
+    /// the least of several such tuples added in the same round is kept.
+    ///
+    /// The projection must preserve the order of the tuples, as
+    /// projecting onto a prefix of their fields does, so that tuples
+    /// with equal projections are adjacent.
+    ///
+    /// This is only sound if no rule depends on which of the tuples
+    /// with equal projections is kept. Typically the fields dropped by
+    /// the projection record provenance, such as how a fact was
+    /// derived, and the variable ends up with one arbitrary provenance
+    /// per fact rather than all of them. A rule that joins on or
+    /// filters by a dropped field may miss results that it would have
+    /// produced from a tuple that was discarded.
+    pub fn dedup_projection<K: Ord>(&self, proj: impl Fn(&Tuple) -> K + 'static) {
+        *self.projection.borrow_mut() = Some(Box::new(move |x, y| proj(x).cmp(&proj(y))));
+    }
+
+    /// Sets whether the variable is maintained distinctly, as variables
+    /// are unless created by `Iteration::variable_indistinct`.
+    ///
+    /// A distinct variable only makes tuples recent that it has not held
+    /// before, whereas one that is not makes recent every tuple that is
+    /// added, even those it already holds. Call this between rounds, not
+    /// while rules are adding tuples, as it decides how the tuples added
+    /// in a round are treated when `changed` is next called.
+    pub fn set_distinct(&self, distinct: bool) {
+        self.distinct.set(distinct);
+    }
+
+    /// Returns the number of stable tuples, those that have already
+    /// been recent.
+    pub fn stable_len(&self) -> usize {
+        self.stable.borrow().iter().map(|batch| batch.len()).sum()
+    }
+
+    /// Returns the number of recent tuples, those that were added in the
+    /// current round.
+    pub fn recent_len(&self) -> usize {
+        self.recent.borrow().len()
+    }
+
     /// Inserts a relation into the variable.
     ///
     /// This is most commonly used to load initial values into a variable.
@@ -493,6 +2697,15 @@ impl<Tuple: Ord> Variable<Tuple> {
         }
     }
 
+    /// Like `insert`, but clones the tuples of `relation`, so that the
+    /// same relation can seed several variables.
+    pub fn insert_ref(&self, relation: &Relation<Tuple>)
+    where
+        Tuple: Clone,
+    {
+        self.insert(relation.clone());
+    }
+
     /// Extend the variable with values from the iterator.
     ///
     /// This is most commonly used to load initial values into a variable.
@@ -505,12 +2718,42 @@ impl<Tuple: Ord> Variable<Tuple> {
         self.insert(iterator.into_iter().collect());
     }
 
+    /// Like `extend`, but for an iterator that produces its tuples in
+    /// sorted order and without duplicates, which spares sorting them.
+    ///
+    /// See `Relation::from_sorted` for the consequences of giving
+    /// other input, which is only checked in debug builds.
+    pub fn extend_sorted(&self, iterator: impl IntoIterator<Item = Tuple>) {
+        self.insert(Relation::from_sorted(iterator.into_iter().collect()));
+    }
+
+    /// Saves the tuples of the variable, stable and recent, without
+    /// consuming it; see `Iteration::variable_from_snapshot`.
+    ///
+    /// Take the snapshot once the iteration has reached a fixpoint, as
+    /// tuples that have not yet been added are not saved.
+    pub fn snapshot(&self) -> RelationSnapshot<Tuple>
+    where
+        Tuple: Clone,
+    {
+        let mut relation = self.recent.borrow().clone();
+        for batch in self.stable.borrow().iter() {
+            relation = relation.merge(batch.clone());
+        }
+        RelationSnapshot { relation }
+    }
+
     /// Consumes the variable and returns a relation.
     ///
     /// This method removes the ability for the variable to develop, and
     /// flattens all internal tuples down to one relation. The method
     /// asserts that iteration has completed, in that `self.recent` and
     /// `self.to_add` should both be empty.
+    ///
+    /// The tuples are returned sorted and distinct, so that the same
+    /// computation always produces the same vector, whatever the order
+    /// in which its tuples were found; this makes it suitable for
+    /// comparing against expected output verbatim.
     pub fn complete(self) -> Relation<Tuple> {
         assert!(self.recent.borrow().is_empty());
         assert!(self.to_add.borrow().is_empty());
@@ -520,19 +2763,101 @@ impl<Tuple: Ord> Variable<Tuple> {
         }
         result
     }
+
+    /// Like `complete`, but releases any space the relation reserved
+    /// beyond its tuples, which merging batches can leave behind.
+    ///
+    /// This is worth the cost of reallocating for relations that are
+    /// kept for a long time.
+    pub fn complete_shrunk(self) -> Relation<Tuple> {
+        let mut result = self.complete();
+        result.shrink_to_fit();
+        result
+    }
+
+    /// Like `complete`, but places the tuples, sorted and distinct, in
+    /// `buffer` rather than in a new relation, replacing its contents.
+    ///
+    /// Reusing one buffer across many iterations spares allocating a
+    /// result for each; the tuples are sorted in place.
+    pub fn complete_into(self, buffer: &mut Vec<Tuple>) {
+        assert!(self.recent.borrow().is_empty());
+        assert!(self.to_add.borrow().is_empty());
+        buffer.clear();
+        for batch in self.stable.borrow_mut().drain(..) {
+            buffer.extend(batch.elements);
+        }
+        buffer.sort();
+        buffer.dedup();
+    }
+
+    /// Like `complete`, but clones the tuples rather than consuming the
+    /// variable, so that every handle to the variable can complete it.
+    pub fn complete_shared(&self) -> Relation<Tuple>
+    where
+        Tuple: Clone,
+    {
+        assert!(self.recent.borrow().is_empty());
+        assert!(self.to_add.borrow().is_empty());
+        let mut result: Relation<Tuple> = Vec::new().into();
+        for batch in self.stable.borrow().iter() {
+            result = result.merge(batch.clone());
+        }
+        result
+    }
+
+    /// Consumes the variable like `complete`, and returns only those
+    /// tuples that are not present in `prior`.
+    ///
+    /// This is useful when re-running a computation with additional
+    /// facts, to find the tuples that are newly derivable.
+    pub fn complete_delta(self, prior: &Relation<Tuple>) -> Relation<Tuple> {
+        let mut result = self.complete();
+        let mut prior = &prior[..];
+        result.elements.retain(|x| {
+            while let Some(y) = prior.first() {
+                match y.cmp(x) {
+                    Ordering::Less => prior = &prior[1..],
+                    Ordering::Equal => return false,
+                    Ordering::Greater => break,
+                }
+            }
+            true
+        });
+        result
+    }
 }
 
 impl<Tuple: Ord> VariableTrait for Variable<Tuple> {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn recent_len(&self) -> usize {
+        Variable::recent_len(self)
+    }
+
+    #[cfg(feature = "trace")]
+    fn stable_len(&self) -> usize {
+        Variable::stable_len(self)
+    }
+
+    fn reset(&self) {
+        self.stable.borrow_mut().clear();
+        *self.recent.borrow_mut() = Vec::new().into();
+        self.to_add.borrow_mut().clear();
+    }
+
     fn changed(&mut self) -> bool {
         // 1. Merge self.recent into self.stable.
         if !self.recent.borrow().is_empty() {
             let mut recent =
-                ::std::mem::replace(&mut (*self.recent.borrow_mut()), Vec::new().into());
+                ::core::mem::replace(&mut (*self.recent.borrow_mut()), Vec::new().into());
             while self
                 .stable
                 .borrow()
                 .last()
-                .map(|x| x.len() <= 2 * recent.len())
+                .map(|x| x.len() <= self.merge_factor * recent.len())
                 == Some(true)
             {
                 let last = self.stable.borrow_mut().pop().unwrap();
@@ -547,8 +2872,13 @@ impl<Tuple: Ord> VariableTrait for Variable<Tuple> {
             while let Some(to_add_more) = self.to_add.borrow_mut().pop() {
                 to_add = to_add.merge(to_add_more);
             }
+            // 2a. Reserve the capacity hint while nothing is stable yet.
+            if self.stable.borrow().is_empty() {
+                let additional = self.capacity.saturating_sub(to_add.elements.len());
+                to_add.elements.reserve(additional);
+            }
             // 2b. Restrict `to_add` to tuples not in `self.stable`.
-            if self.distinct {
+            if self.distinct.get() {
                 for batch in self.stable.borrow().iter() {
                     let mut slice = &batch[..];
                     // Only gallop if the slice is relatively large.
@@ -558,15 +2888,32 @@ impl<Tuple: Ord> VariableTrait for Variable<Tuple> {
                             slice.is_empty() || &slice[0] != x
                         });
                     } else {
+                        // Compare each pair of tuples only once, as wide
+                        // tuples may be costly to compare.
                         to_add.elements.retain(|x| {
-                            while !slice.is_empty() && &slice[0] < x {
-                                slice = &slice[1..];
+                            while let Some(y) = slice.first() {
+                                match y.cmp(x) {
+                                    Ordering::Less => slice = &slice[1..],
+                                    Ordering::Equal => return false,
+                                    Ordering::Greater => break,
+                                }
                             }
-                            slice.is_empty() || &slice[0] != x
+                            true
                         });
                     }
                 }
             }
+            // 2c. Keep one tuple per projection, if there is one.
+            if let Some(cmp) = &*self.projection.borrow() {
+                to_add
+                    .elements
+                    .dedup_by(|x, y| cmp(x, y) == Ordering::Equal);
+                for batch in self.stable.borrow().iter() {
+                    to_add
+                        .elements
+                        .retain(|x| batch.binary_search_by(|y| cmp(y, x)).is_err());
+                }
+            }
             *self.recent.borrow_mut() = to_add;
         }
 
@@ -1,5 +1,7 @@
 //! Map functionality.
 
+use alloc::vec::Vec;
+
 use super::{Relation, Variable};
 
 pub(crate) fn map_into<T1: Ord, T2: Ord>(
@@ -11,3 +13,46 @@ pub(crate) fn map_into<T1: Ord, T2: Ord>(
 
     output.insert(Relation::from_vec(results));
 }
+
+pub(crate) fn map_filter_into<T1: Ord, T2: Ord>(
+    input: &Variable<T1>,
+    output: &Variable<T2>,
+    logic: impl FnMut(&T1) -> Option<T2>,
+) {
+    let results: Vec<T2> = input.recent.borrow().iter().filter_map(logic).collect();
+
+    output.insert(Relation::from_vec(results));
+}
+
+pub(crate) fn map_expand_into<K: Ord, V: Ord, T2: Ord>(
+    input: &Variable<K>,
+    output: &Variable<T2>,
+    expand: impl Fn(&K) -> Relation<V>,
+    mut logic: impl FnMut(&K, &V) -> T2,
+) {
+    let mut results = Vec::new();
+    for key in input.recent.borrow().iter() {
+        for val in expand(key).iter() {
+            results.push(logic(key, val));
+        }
+    }
+
+    output.insert(Relation::from_vec(results));
+}
+
+pub(crate) fn map_lookup_into<T1: Ord, K: Ord, V: Ord, T2: Ord>(
+    input: &Variable<T1>,
+    output: &Variable<T2>,
+    table: &Relation<(K, V)>,
+    key_fn: impl Fn(&T1) -> &K,
+    mut logic: impl FnMut(&T1, Option<&V>) -> T2,
+) {
+    let results: Vec<T2> = input
+        .recent
+        .borrow()
+        .iter()
+        .map(|tuple| logic(tuple, table.values_for(key_fn(tuple)).next()))
+        .collect();
+
+    output.insert(Relation::from_vec(results));
+}
@@ -0,0 +1,46 @@
+//! Parallel sorting of large relations, with the `rayon` feature.
+
+use alloc::vec::Vec;
+
+use rayon::prelude::*;
+
+/// The number of tuples above which relations are sorted and
+/// deduplicated in parallel; for fewer, the cost of spreading the work
+/// across threads outweighs the benefit.
+pub(crate) const THRESHOLD: usize = 100_000;
+
+/// Sorts `elements` and removes duplicates, on several threads.
+pub(crate) fn sort_dedup<Tuple: Ord + Send>(elements: &mut Vec<Tuple>) {
+    elements.par_sort_unstable();
+
+    // Remove duplicates within each chunk, moving the distinct tuples
+    // to its front, and count them.
+    let chunk_len = (elements.len() / rayon::current_num_threads()).max(THRESHOLD);
+    let distinct: Vec<usize> = elements
+        .par_chunks_mut(chunk_len)
+        .map(|chunk| {
+            let mut kept = 1;
+            for index in 1..chunk.len() {
+                if chunk[index] != chunk[kept - 1] {
+                    chunk.swap(index, kept);
+                    kept += 1;
+                }
+            }
+            kept
+        })
+        .collect();
+
+    // Move the distinct tuples of each chunk after those of the chunks
+    // before it; only its first tuple may equal the last tuple kept.
+    let mut kept = 0;
+    for (chunk, &distinct) in distinct.iter().enumerate() {
+        let start = chunk * chunk_len;
+        for index in start..start + distinct {
+            if kept == 0 || elements[index] != elements[kept - 1] {
+                elements.swap(index, kept);
+                kept += 1;
+            }
+        }
+    }
+    elements.truncate(kept);
+}
@@ -140,7 +140,7 @@ proptest! {
         );
 
         let intersection2: Relation<(u32, u32)> = input1.elements.iter()
-            .filter(|t| input2.elements.binary_search(&t).is_ok())
+            .filter(|t| input2.elements.binary_search(t).is_ok())
             .collect();
 
         assert_eq!(intersection1.elements, intersection2.elements);
@@ -160,13 +160,55 @@ proptest! {
         );
 
         let difference2: Relation<(u32, u32)> = input1.elements.iter()
-            .filter(|t| input2.elements.binary_search(&t).is_err())
+            .filter(|t| input2.elements.binary_search(t).is_err())
             .collect();
 
         assert_eq!(difference1.elements, difference2.elements);
     }
 }
 
+/// Computes `triangle(X, Y, Z) :- a(X, Y), b(Y, Z), c(X, Z), X != Z`
+/// as a chain of binary joins, materializing the join of `a` and `b`.
+fn triangles_via_join_chain(
+    a: &Relation<(u32, u32)>,
+    b: &Relation<(u32, u32)>,
+    c: &Relation<(u32, u32)>,
+) -> Relation<(u32, u32, u32)> {
+    let a_by_y = Relation::from_map(a, |&(x, y)| (y, x));
+    let ab = Relation::from_join(&a_by_y, b, |&y, &x, &z| ((x, z), y));
+    let c_keyed = Relation::from_map(c, |&(x, z)| ((x, z), ()));
+    let abc = Relation::from_join(&ab, &c_keyed, |&(x, z), &y, &()| (x, y, z));
+    Relation::from_iter(abc.iter().filter(|&&(x, _, z)| x != z).cloned())
+}
+
+/// Computes the same triangles as `triangles_via_join_chain` with a
+/// single leapjoin, without materializing intermediate results.
+fn triangles_via_leapjoin(
+    a: &Relation<(u32, u32)>,
+    b: &Relation<(u32, u32)>,
+    c: &Relation<(u32, u32)>,
+) -> Relation<(u32, u32, u32)> {
+    Relation::from_leapjoin(
+        a,
+        (
+            b.extend_with(|&(_, y)| y),
+            c.extend_with(|&(x, _)| x),
+            crate::ValueFilter::from(|&(x, _), &z| x != z),
+        ),
+        |&(x, y), &z| (x, y, z),
+    )
+}
+
+proptest! {
+    #[test]
+    fn triangles_leapjoin_vs_join_chain((a, b, c) in (inputs(), inputs(), inputs())) {
+        let (a, b, c): (Relation<_>, Relation<_>, Relation<_>) = (a.into(), b.into(), c.into());
+        let triangles1 = triangles_via_join_chain(&a, &b, &c);
+        let triangles2 = triangles_via_leapjoin(&a, &b, &c);
+        assert_eq!(triangles1.elements, triangles2.elements);
+    }
+}
+
 /// Test that `from_leapjoin` matches against the tuples from an
 /// `extend` that precedes first iteration.
 ///
@@ -193,3 +235,2415 @@ fn leapjoin_from_extend() {
 
     assert_eq!(variable.elements, vec![(2, 2), (2, 4)]);
 }
+
+/// Test that `from_map_expand` produces one tuple per expanded value
+/// of each key.
+#[test]
+fn map_expand_per_key() {
+    let mut iteration = Iteration::new();
+
+    let keys = iteration.variable::<u32>();
+    keys.extend(1..5);
+
+    let expanded = iteration.variable::<(u32, u32)>();
+
+    while iteration.changed() {
+        expanded.from_map_expand(&keys, |&k| (0..k).collect(), |&k, &v| (k, v));
+    }
+
+    let expanded = expanded.complete();
+    let expected: Vec<(u32, u32)> = (1..5).flat_map(|k| (0..k).map(move |v| (k, v))).collect();
+    assert_eq!(expanded.elements, expected);
+}
+
+#[cfg(feature = "testing")]
+#[test]
+fn assert_relation_eq_on_equal_sets() {
+    let relation: Relation<(u32, u32)> = vec![(1, 2), (2, 3)].into();
+    crate::assert_relation_eq!(relation, vec![(2, 3), (1, 2), (2, 3)]);
+    crate::assert_relation_eq!(Relation::<u32>::from_vec(vec![]), []);
+}
+
+#[cfg(feature = "testing")]
+#[test]
+#[should_panic(expected = "   missing: [(3, 4)]\nunexpected: [(1, 2)]")]
+fn assert_relation_eq_on_unequal_sets() {
+    let relation: Relation<(u32, u32)> = vec![(1, 2), (2, 3)].into();
+    crate::assert_relation_eq!(relation, [(2, 3), (3, 4)]);
+}
+
+/// Test that `changed_observing_deltas` reports the tuples promoted in
+/// each round of a transitive closure.
+#[test]
+fn observe_deltas_of_transitive_closure() {
+    let edges: Relation<(u32, u32)> = vec![(2, 3), (3, 4)].into();
+
+    let mut iteration = Iteration::new();
+
+    // reach(B, A) means that B is reachable from A.
+    let reach = iteration.variable_named::<(u32, u32)>("reach");
+    reach.extend(vec![(2, 1), (3, 2), (4, 3)]);
+    iteration.observe(&reach);
+
+    let mut deltas = Vec::new();
+    while iteration.changed_observing_deltas(|name, tuples| {
+        let tuples: Vec<(u32, u32)> = tuples
+            .iter()
+            .map(|tuple| *tuple.downcast_ref::<(u32, u32)>().unwrap())
+            .collect();
+        deltas.push((name.to_string(), tuples));
+    }) {
+        // reach(C, A) :- reach(B, A), edges(B, C).
+        reach.from_join(&reach, &edges, |_, &a, &c| (c, a));
+    }
+
+    let reach = |tuples: Vec<(u32, u32)>| ("reach".to_string(), tuples);
+    assert_eq!(
+        deltas,
+        vec![
+            reach(vec![(2, 1), (3, 2), (4, 3)]),
+            reach(vec![(3, 1), (4, 2)]),
+            reach(vec![(4, 1)]),
+            reach(vec![]),
+        ]
+    );
+}
+
+#[test]
+fn join_grouped_small() {
+    let left: Relation<(u32, char)> = vec![(1, 'a'), (1, 'b'), (2, 'c'), (4, 'd')].into();
+    let right: Relation<(u32, u32)> = vec![(1, 10), (2, 20), (2, 21), (3, 30)].into();
+
+    let grouped = left.join_grouped(&right);
+
+    assert_eq!(
+        grouped.elements,
+        vec![
+            (1, (vec!['a', 'b'], vec![10])),
+            (2, (vec!['c'], vec![20, 21]))
+        ]
+    );
+}
+
+/// Joins wide tuples on their first field; tuples sharing a key only
+/// differ in their last field, so comparing them whole is costly.
+#[test]
+fn join_wide_tuples_on_key() {
+    type Wide = [u32; 16];
+    let wide = |i: u32| -> Wide {
+        let mut tuple = [0; 16];
+        tuple[0] = i % 100;
+        tuple[15] = i;
+        tuple
+    };
+
+    let mut iteration = Iteration::new();
+
+    let input1 = iteration.variable::<Wide>();
+    input1.extend((0..1000).map(wide));
+
+    let input2 = iteration.variable::<Wide>();
+    input2.extend((0..1000).map(|i| wide(i * 7)));
+
+    let output = iteration.variable::<(u32, u32)>();
+
+    while iteration.changed() {
+        output.from_join_adv(
+            &input1,
+            &input2,
+            |t| &t[0],
+            |t| &t[0],
+            |_, t1, t2| (t1[15], t2[15]),
+        );
+
+        // Re-deriving the same tuples exercises the distinctness check.
+        input1.from_map(&input1, |&t| t);
+    }
+
+    let mut expected = Vec::new();
+    for i in 0..1000 {
+        for j in 0..1000 {
+            if i % 100 == (j * 7) % 100 {
+                expected.push((i, j * 7));
+            }
+        }
+    }
+    expected.sort();
+
+    assert_eq!(output.complete().elements, expected);
+    assert_eq!(input1.complete().len(), 1000);
+}
+
+#[test]
+fn remove_keys_drops_whole_key_groups() {
+    let relation: Relation<(u32, char)> = vec![(1, 'a'), (2, 'b'), (2, 'c'), (3, 'd')].into();
+    let keys: Relation<u32> = vec![2].into();
+
+    let relation = relation.remove_keys(&keys);
+    assert_eq!(relation.elements, vec![(1, 'a'), (3, 'd')]);
+}
+
+#[test]
+fn join_converting_keys() {
+    let mut iteration = Iteration::new();
+
+    let input1 = iteration.variable::<(u32, char)>();
+    input1.extend(vec![(1, 'a'), (2, 'b'), (3, 'c')]);
+
+    let input2 = iteration.variable::<(u64, char)>();
+    input2.extend(vec![(2, 'x'), (3, 'y'), (3, 'z'), (1 << 32, 'w')]);
+
+    let output = iteration.variable::<(u64, char, char)>();
+
+    while iteration.changed() {
+        output.from_join_convert_key(
+            &input1,
+            &input2,
+            |&k1| u64::from(k1),
+            |&k2| k2,
+            |&k, &v1, &v2| (k, v1, v2),
+        );
+    }
+
+    assert_eq!(
+        output.complete().elements,
+        vec![(2, 'b', 'x'), (3, 'c', 'y'), (3, 'c', 'z')]
+    );
+}
+
+#[test]
+fn missing_keys_in_range() {
+    let relation: Relation<(u64, char)> = vec![(1, 'a'), (3, 'b'), (3, 'c'), (7, 'd')].into();
+
+    assert_eq!(relation.missing_keys(1..4), vec![2]);
+    assert_eq!(relation.missing_keys(0..9), vec![0, 2, 4, 5, 6, 8]);
+    assert_eq!(relation.missing_keys(3..3), Vec::<u64>::new());
+}
+
+#[test]
+fn complete_delta_after_new_fact() {
+    let reachable = |edges: &[(u32, u32)]| {
+        let edges: Relation<_> = edges.iter().map(|&(a, b)| (b, a)).collect();
+        let mut iteration = Iteration::new();
+        let reachable = iteration.variable::<(u32, u32)>();
+        reachable.extend(edges.iter().map(|&(b, a)| (a, b)));
+        while iteration.changed() {
+            reachable.from_join(&reachable, &edges, |_, &c, &a| (a, c));
+        }
+        reachable
+    };
+
+    let prior = reachable(&[(1, 2), (2, 3)]).complete();
+    assert_eq!(prior.elements, vec![(1, 2), (1, 3), (2, 3)]);
+
+    let delta = reachable(&[(1, 2), (2, 3), (3, 4)]).complete_delta(&prior);
+    assert_eq!(delta.elements, vec![(1, 4), (2, 4), (3, 4)]);
+}
+
+#[test]
+fn record_inserts_per_round() {
+    let edges: Relation<(u32, u32)> = vec![(2, 3), (3, 4)].into();
+
+    let mut iteration = Iteration::new();
+
+    // reach(B, A) means that B is reachable from A.
+    let reach = iteration.variable_named::<(u32, u32)>("reach");
+    reach.extend(vec![(2, 1), (3, 2), (4, 3)]);
+
+    let sources = iteration.variable_named::<u32>("sources");
+    iteration.record(&reach);
+    iteration.record(&sources);
+
+    while iteration.changed() {
+        // reach(C, A) :- reach(B, A), edges(B, C).
+        reach.from_join(&reach, &edges, |_, &a, &c| (c, a));
+        sources.from_map(&reach, |&(_, a)| a);
+    }
+
+    let inserts: Vec<Vec<(&str, usize)>> = iteration
+        .trace()
+        .iter()
+        .map(|round| {
+            round
+                .iter()
+                .map(|insert| (&insert.variable[..], insert.tuples))
+                .collect()
+        })
+        .collect();
+
+    assert_eq!(
+        inserts,
+        vec![
+            vec![("reach", 3)],
+            vec![("reach", 2), ("sources", 3)],
+            vec![("reach", 1), ("sources", 2)],
+            vec![("sources", 1)],
+        ]
+    );
+
+    let first = iteration.trace()[0][0].relation::<(u32, u32)>().unwrap();
+    assert_eq!(first.elements, vec![(2, 1), (3, 2), (4, 3)]);
+    assert!(iteration.trace()[0][0].relation::<u32>().is_none());
+
+    // Inserting the recorded relations round by round replays `reach`.
+    let mut replay = Iteration::new();
+    let replayed = replay.variable::<(u32, u32)>();
+    for round in iteration.trace() {
+        for insert in round.iter().filter(|insert| insert.variable == "reach") {
+            replayed.insert(insert.relation::<(u32, u32)>().unwrap().clone());
+        }
+        replay.changed();
+    }
+    while replay.changed() {}
+    assert_eq!(replayed.complete().elements, reach.complete().elements);
+}
+
+#[test]
+fn relation_with_capacity() {
+    let relation = Relation::<(u32, u32)>::with_capacity(100);
+    assert!(relation.is_empty());
+    assert!(relation.elements.capacity() >= 100);
+}
+
+#[test]
+fn declare_variables_with_macro() {
+    let edges: Relation<(u32, u32)> = vec![(1, 2), (2, 3), (3, 4)].into();
+
+    let mut iteration = Iteration::new();
+    crate::variables!(
+        iteration;
+        edges_by_successor: (u32, u32) = Relation::from_map(&edges, |&(a, b)| (b, a)),
+        reach: (u32, u32) = edges.clone(),
+        sources: u32,
+    );
+
+    while iteration.changed() {
+        // reach(A, C) :- edges(A, B), reach(B, C).
+        reach.from_join(&reach, &edges_by_successor, |_, &c, &a| (a, c));
+        sources.from_map(&reach, |&(a, _)| a);
+    }
+
+    assert_eq!(reach.name(), "reach");
+    assert_eq!(
+        reach.complete().elements,
+        vec![(1, 2), (1, 3), (1, 4), (2, 3), (2, 4), (3, 4)]
+    );
+    assert_eq!(sources.complete().elements, vec![1, 2, 3]);
+}
+
+#[test]
+fn join_against_sorted_slice() {
+    let successors: &[(u32, u32)] = &[(1, 2), (2, 3), (2, 4)];
+    let excluded: &[u32] = &[3];
+
+    let mut iteration = Iteration::new();
+
+    let reach = iteration.variable::<(u32, u32)>();
+    reach.extend(vec![(1, 0)]);
+
+    let allowed = iteration.variable::<(u32, u32)>();
+
+    while iteration.changed() {
+        // reach(C, A) :- reach(B, A), successors(B, C).
+        reach.from_join_slice(&reach, successors, |_, &a, &c| (c, a));
+
+        // allowed(B, A) :- reach(B, A), !excluded(B).
+        allowed.from_antijoin_slice(&reach, excluded, |&b, &a| (b, a));
+    }
+
+    assert_eq!(
+        reach.complete().elements,
+        vec![(1, 0), (2, 0), (3, 0), (4, 0)]
+    );
+    assert_eq!(allowed.complete().elements, vec![(1, 0), (2, 0), (4, 0)]);
+}
+
+#[test]
+fn retain_key_range_window() {
+    let relation: Relation<(u32, char)> =
+        vec![(1, 'a'), (2, 'b'), (2, 'c'), (3, 'd'), (4, 'e'), (5, 'f')].into();
+
+    let window = relation.clone().retain_key_range(2..4);
+    assert_eq!(window.elements, vec![(2, 'b'), (2, 'c'), (3, 'd')]);
+
+    assert!(relation.clone().retain_key_range(6..9).is_empty());
+    assert!(relation.retain_key_range(3..3).is_empty());
+}
+
+#[test]
+fn binary_map_one_to_one() {
+    let mut iteration = Iteration::new();
+
+    let names = iteration.variable::<(u32, &str)>();
+    names.extend(vec![(1, "one"), (2, "two"), (3, "three")]);
+
+    let squares = iteration.variable::<(u32, u32)>();
+    squares.extend(vec![(2, 4), (3, 9), (4, 16)]);
+
+    let output = iteration.variable::<(&str, u32)>();
+
+    while iteration.changed() {
+        output.from_binary_map(&names, &squares, |_, &name, &square| (name, square));
+    }
+
+    assert_eq!(output.complete().elements, vec![("three", 9), ("two", 4)]);
+}
+
+#[cfg(debug_assertions)]
+#[test]
+#[should_panic(expected = "key is not unique")]
+fn binary_map_duplicate_key() {
+    let mut iteration = Iteration::new();
+
+    let input1 = iteration.variable::<(u32, u32)>();
+    input1.extend(vec![(1, 1), (1, 2)]);
+
+    let input2 = iteration.variable::<(u32, u32)>();
+    input2.extend(vec![(1, 3)]);
+
+    let output = iteration.variable::<(u32, u32, u32)>();
+
+    while iteration.changed() {
+        output.from_binary_map(&input1, &input2, |&k, &v1, &v2| (k, v1, v2));
+    }
+}
+
+#[cfg(debug_assertions)]
+#[test]
+#[should_panic(expected = "key is not unique")]
+fn binary_map_duplicate_key_in_later_round() {
+    let mut iteration = Iteration::new();
+
+    let input1 = iteration.variable::<(u32, u32)>();
+    input1.extend(vec![(1, 1)]);
+
+    let input2 = iteration.variable::<(u32, u32)>();
+    input2.extend(vec![(1, 3)]);
+
+    let output = iteration.variable::<(u32, u32, u32)>();
+
+    // Key 1 gains a second value on the left once the first is stable,
+    // so that no single batch holds it twice.
+    while iteration.changed() {
+        input1.from_map(&input1, |&(k, v)| (k, v.max(2)));
+        output.from_binary_map(&input1, &input2, |&k, &v1, &v2| (k, v1, v2));
+    }
+}
+
+#[test]
+fn stable_dedup_keeps_first_occurrence() {
+    let deduped = Relation::stable_dedup_by_insertion(vec![3, 1, 3, 2, 1, 4, 2]);
+    assert_eq!(deduped, vec![3, 1, 2, 4]);
+
+    assert!(Relation::<u32>::stable_dedup_by_insertion(vec![]).is_empty());
+}
+
+#[test]
+fn import_variable_from_other_iteration() {
+    let edges: Relation<(u32, u32)> = vec![(1, 2), (2, 1), (2, 3)].into();
+
+    // Program A: reach(A, C) :- edges(A, B), reach(B, C).
+    let mut program_a = Iteration::new();
+    let edges_by_successor = Relation::from_map(&edges, |&(a, b)| (b, a));
+    let reach = program_a.variable_named::<(u32, u32)>("reach");
+    reach.insert(edges.clone());
+    while program_a.changed() {
+        reach.from_join(&reach, &edges_by_successor, |_, &c, &a| (a, c));
+    }
+
+    // Program B: reached_from(C, A) :- reach(A, C).
+    let mut program_b = Iteration::new();
+    let reach = program_b.import(reach);
+    let reached_from = program_b.variable_named::<(u32, u32)>("reached_from");
+    while program_b.changed() {
+        reached_from.from_map(&reach, |&(a, c)| (c, a));
+    }
+
+    assert_eq!(reach.name(), "reach");
+    assert_eq!(
+        reach.complete().elements,
+        vec![(1, 1), (1, 2), (1, 3), (2, 1), (2, 2), (2, 3)]
+    );
+    assert_eq!(
+        reached_from.complete().elements,
+        vec![(1, 1), (1, 2), (2, 1), (2, 2), (3, 1), (3, 2)]
+    );
+}
+
+#[test]
+fn permute_columns_re_sorts() {
+    let relation: Relation<[u32; 3]> = vec![[1, 9, 5], [2, 8, 4], [3, 7, 6]].into();
+
+    let permuted = relation.permute_columns([2, 0, 1]);
+    assert_eq!(permuted.elements, vec![[4, 2, 8], [5, 1, 9], [6, 3, 7]]);
+}
+
+#[test]
+#[should_panic(expected = "is not a permutation")]
+fn permute_columns_rejects_non_permutation() {
+    let relation: Relation<[u32; 3]> = vec![[1, 2, 3]].into();
+    relation.permute_columns([0, 0, 1]);
+}
+
+#[test]
+fn find_cycles_reports_only_cyclic_nodes() {
+    // 1 -> 2 -> 3 -> 1 is a cycle; 0 leads into it and 4 leads out of it.
+    let edges: Relation<(u32, u32)> = vec![(0, 1), (1, 2), (2, 3), (3, 1), (3, 4)].into();
+
+    assert_eq!(crate::find_cycles(edges).elements, vec![1, 2, 3]);
+}
+
+#[test]
+fn indexed_relation_groups_match_scan() {
+    let relation: Relation<(u32, u32)> = (0..100).map(|i| (i % 7 * 3, i)).collect();
+    let indexed = crate::IndexedRelation::new(relation.clone());
+
+    for key in 0..25 {
+        let scanned: Vec<(u32, u32)> = relation
+            .iter()
+            .filter(|(k, _)| *k == key)
+            .cloned()
+            .collect();
+        assert_eq!(indexed.group(&key), &scanned[..]);
+    }
+}
+
+#[test]
+fn join_against_indexed_relation() {
+    let edges: Relation<(u32, u32)> = vec![(1, 2), (2, 3), (2, 4), (4, 5)].into();
+    let indexed = crate::IndexedRelation::new(edges);
+
+    let mut iteration = Iteration::new();
+
+    // reach(B, A) means that B is reachable from A.
+    let reach = iteration.variable::<(u32, u32)>();
+    reach.extend(vec![(1, 1)]);
+
+    while iteration.changed() {
+        // reach(C, A) :- reach(B, A), edges(B, C).
+        reach.from_join_indexed(&reach, &indexed, |_, &a, &c| (c, a));
+    }
+
+    assert_eq!(
+        reach.complete().elements,
+        vec![(1, 1), (2, 1), (3, 1), (4, 1), (5, 1)]
+    );
+}
+
+#[test]
+fn enumerate_tuples() {
+    let relation: Relation<&str> = vec!["c", "a", "b"].into();
+    assert_eq!(
+        relation.enumerate().elements,
+        vec![(0, "a"), (1, "b"), (2, "c")]
+    );
+}
+
+/// Test that `close_under` computes the same Collatz journeys as the
+/// `from_map` example.
+#[test]
+fn close_under_collatz() {
+    let mut iteration = Iteration::new();
+
+    let variable = iteration.variable::<(usize, usize)>();
+    variable.extend((0..10).map(|x| (x, x)));
+
+    while iteration.changed() {
+        variable.close_under(|&(key, val)| {
+            if val % 2 == 0 {
+                Some((key, val / 2))
+            } else {
+                Some((key, 3 * val + 1))
+            }
+        });
+    }
+
+    assert_eq!(variable.complete().len(), 74);
+}
+
+#[test]
+fn complete_shared_through_cloned_handles() {
+    let mut iteration = Iteration::new();
+
+    let variable = iteration.variable::<(u32, u32)>();
+    variable.extend((0..10).map(|x| (x, x + 1)));
+    let handle = variable.clone();
+
+    while iteration.changed() {
+        variable.from_map(&variable, |&(x, y)| (x, y % 5));
+    }
+
+    let result1 = variable.complete_shared();
+    let result2 = handle.complete_shared();
+    assert_eq!(result1.len(), 16);
+    assert_eq!(result1.elements, result2.elements);
+    assert_eq!(handle.complete().elements, result1.elements);
+}
+
+#[test]
+fn join_filtered_by_weight() {
+    // edge(A, B, W) is stored as (A, (B, W)).
+    let edges: Relation<(u32, (u32, u32))> =
+        vec![(1, (2, 5)), (2, (3, 1)), (2, (4, 7)), (3, (4, 2))].into();
+
+    let mut iteration = Iteration::new();
+
+    // path(B, A, W) is stored as (B, (A, W)): a path from A to B of weight W.
+    let path = iteration.variable::<(u32, (u32, u32))>();
+    path.extend(edges.iter().map(|&(a, (b, w))| (b, (a, w))));
+
+    while iteration.changed() {
+        // path(C, A, W1 + W2) :- path(B, A, W1), edge(B, C, W2), W1 + W2 < 10.
+        path.from_join_filtered(&path, &edges, |_, &(a, w1), &(c, w2)| {
+            if w1 + w2 < 10 {
+                Some((c, (a, w1 + w2)))
+            } else {
+                None
+            }
+        });
+    }
+
+    assert_eq!(
+        path.complete().elements,
+        vec![
+            (2, (1, 5)),
+            (3, (1, 6)),
+            (3, (2, 1)),
+            (4, (1, 8)),
+            (4, (2, 3)),
+            (4, (2, 7)),
+            (4, (3, 2))
+        ]
+    );
+}
+
+#[test]
+fn transitive_reduction_of_dag() {
+    // 1 -> 2 -> 3 -> 4, plus the redundant shortcuts 1 -> 3 and 1 -> 4,
+    // and 2 -> 5 which is not implied by anything.
+    let edges: Relation<(u32, u32)> = vec![(1, 2), (2, 3), (3, 4), (1, 3), (1, 4), (2, 5)].into();
+
+    assert_eq!(
+        crate::transitive_reduction(edges).elements,
+        vec![(1, 2), (2, 3), (2, 5), (3, 4)]
+    );
+}
+
+#[test]
+fn contains_and_get_range() {
+    let empty: Relation<(u32, u32)> = vec![].into();
+    assert!(!empty.contains(&(1, 1)));
+    assert!(empty.get_range(&1, |(k, _)| k).is_empty());
+
+    let single: Relation<(u32, u32)> = vec![(1, 2)].into();
+    assert!(single.contains(&(1, 2)));
+    assert!(!single.contains(&(1, 3)));
+    assert_eq!(single.get_range(&1, |(k, _)| k), &[(1, 2)]);
+    assert!(single.get_range(&0, |(k, _)| k).is_empty());
+    assert!(single.get_range(&2, |(k, _)| k).is_empty());
+
+    let many: Relation<(u32, u32)> = vec![(1, 1), (2, 1), (2, 2), (2, 3), (4, 1)].into();
+    assert!(many.contains(&(2, 3)));
+    assert!(!many.contains(&(3, 1)));
+    assert_eq!(many.get_range(&2, |(k, _)| k), &[(2, 1), (2, 2), (2, 3)]);
+    assert_eq!(many.get_range(&4, |(k, _)| k), &[(4, 1)]);
+    assert!(many.get_range(&3, |(k, _)| k).is_empty());
+}
+
+#[test]
+fn intersect_and_difference() {
+    let evens: Relation<u32> = (0..10).map(|x| x * 2).collect();
+    let odds: Relation<u32> = (0..10).map(|x| x * 2 + 1).collect();
+    let small: Relation<u32> = (0..10).collect();
+
+    // Disjoint relations.
+    assert!(evens.intersect(&odds).is_empty());
+    assert_eq!(evens.difference(&odds).elements, evens.elements);
+
+    // Identical relations.
+    assert_eq!(evens.intersect(&evens).elements, evens.elements);
+    assert!(evens.difference(&evens).is_empty());
+
+    // Partially overlapping relations.
+    assert_eq!(evens.intersect(&small).elements, vec![0, 2, 4, 6, 8]);
+    assert_eq!(small.intersect(&evens).elements, vec![0, 2, 4, 6, 8]);
+    assert_eq!(small.difference(&evens).elements, vec![1, 3, 5, 7, 9]);
+    assert_eq!(evens.difference(&small).elements, vec![10, 12, 14, 16, 18]);
+}
+
+#[test]
+fn transitively_close_matches_manual_closure() {
+    let edges: Vec<(u32, u32)> = vec![(1, 2), (2, 3), (3, 1), (3, 4), (5, 6), (6, 7)];
+
+    let helper = {
+        let mut iteration = Iteration::new();
+        let reach = iteration.variable::<(u32, u32)>();
+        reach.extend(edges.iter().cloned());
+        while iteration.changed() {
+            reach.transitively_close(|(a, b)| (a, b), |&(a, _), &(_, c)| (a, c));
+        }
+        reach.complete()
+    };
+
+    let manual = {
+        let mut iteration = Iteration::new();
+        let edges = Relation::from_iter(edges.iter().cloned());
+        let reach = iteration.variable::<(u32, u32)>();
+        let reach_by_target = iteration.variable::<(u32, u32)>();
+        reach.insert(edges.clone());
+        while iteration.changed() {
+            reach_by_target.from_map(&reach, |&(a, b)| (b, a));
+            reach.from_join(&reach_by_target, &edges, |_, &a, &c| (a, c));
+        }
+        reach.complete()
+    };
+
+    assert_eq!(helper.elements, manual.elements);
+}
+
+#[test]
+fn filter_map_ordered() {
+    let relation: Relation<(u32, u32)> = (0..10).map(|x| (x / 3, x)).collect();
+
+    // Keeping the even values and dropping the key preserves the order.
+    let evens = relation.filter_map_ordered(|&(_, v)| if v % 2 == 0 { Some(v) } else { None });
+    assert_eq!(evens.elements, vec![0, 2, 4, 6, 8]);
+
+    // Projecting onto the leading field preserves the order, and the
+    // repeated keys are collapsed.
+    let keys = relation.filter_map_ordered(|&(k, _)| Some(k));
+    assert_eq!(keys.elements, vec![0, 1, 2, 3]);
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn serde_round_trip() {
+    let edges: Relation<(u32, u32)> = (0..10).map(|x| (x, x + 1)).collect();
+    let json = serde_json::to_string(&edges).unwrap();
+    let decoded: Relation<(u32, u32)> = serde_json::from_str(&json).unwrap();
+    assert_eq!(decoded.elements, edges.elements);
+
+    // Unsorted input with duplicates is normalized.
+    let decoded: Relation<(u32, u32)> = serde_json::from_str("[[2,1],[1,2],[2,1]]").unwrap();
+    assert_eq!(decoded.elements, vec![(1, 2), (2, 1)]);
+}
+
+#[test]
+fn nested_reachability_per_start_node() {
+    let edges: Relation<(u32, u32)> = vec![(1, 2), (2, 3), (3, 1), (4, 2), (5, 6)].into();
+    let starts: Relation<u32> = vec![1, 4, 5].into();
+
+    let reachable = crate::nested_iteration(&starts, |&start, iteration| {
+        let reached = iteration.variable::<u32>();
+        let reached_keyed = iteration.variable::<(u32, ())>();
+        reached.insert(vec![start].into());
+        while iteration.changed() {
+            reached_keyed.from_map(&reached, |&node| (node, ()));
+            reached.from_join(&reached_keyed, &edges, |_, &(), &next| next);
+        }
+        Relation::from_map(&reached.complete(), |&node| (start, node))
+    });
+
+    let expected: Vec<(u32, u32)> = vec![
+        (1, 1),
+        (1, 2),
+        (1, 3),
+        (4, 1),
+        (4, 2),
+        (4, 3),
+        (4, 4),
+        (5, 5),
+        (5, 6),
+    ];
+    assert_eq!(reachable.elements, expected);
+}
+
+#[test]
+fn from_map_filtered_drops_tuples() {
+    // Collatz journeys, dropping the pairs whose value exceeds a bound.
+    let mut iteration = Iteration::new();
+    let variable = iteration.variable::<(u32, u32)>();
+    variable.extend((1..10).map(|x| (x, x)));
+
+    while iteration.changed() {
+        variable.from_map_filtered(&variable, |&(key, val)| {
+            let next = if val % 2 == 0 { val / 2 } else { 3 * val + 1 };
+            if next <= 16 {
+                Some((key, next))
+            } else {
+                None
+            }
+        });
+    }
+
+    let result = variable.complete();
+    assert!(result.iter().all(|&(_, val)| val <= 16));
+    assert!(result.contains(&(5, 16)));
+    assert!(!result.contains(&(7, 22)));
+    assert_eq!(result.iter().filter(|&&(key, _)| key == 7).count(), 1);
+}
+
+#[test]
+fn join_with_miss_count() {
+    let left: Relation<(u32, u32)> = vec![(1, 10), (1, 11), (2, 20), (3, 30), (5, 50)].into();
+    let right: Relation<(u32, char)> = vec![(1, 'a'), (3, 'c'), (3, 'C'), (4, 'd')].into();
+
+    let (joined, misses) = left.join_with_miss_count(&right, |&k, &v1, &v2| (k, v1, v2));
+    assert_eq!(
+        joined.elements,
+        vec![(1, 10, 'a'), (1, 11, 'a'), (3, 30, 'C'), (3, 30, 'c')]
+    );
+    assert_eq!(misses, 2);
+
+    let (joined, misses) =
+        left.join_with_miss_count(&Relation::<(u32, char)>::from_vec(vec![]), |&k, _, _| k);
+    assert!(joined.is_empty());
+    assert_eq!(misses, left.len());
+}
+
+#[test]
+fn from_antijoin_by_non_leading_field() {
+    let mut iteration = Iteration::new();
+    let input = iteration.variable::<(u32, u32, u32)>();
+    let by_middle = iteration.variable::<(u32, u32)>();
+    let by_last = iteration.variable::<(u32, u32, u32)>();
+    input.extend(vec![(1, 5, 9), (2, 4, 8), (3, 5, 7), (4, 6, 6)]);
+
+    let anti: Relation<u32> = vec![5, 6].into();
+    while iteration.changed() {
+        by_middle.from_antijoin_by(&input, &anti, |(_, b, _)| b, |&(a, _, c)| (a, c));
+        by_last.from_antijoin_by(&input, &anti, |(_, _, c)| c, |&t| t);
+    }
+
+    assert_eq!(by_middle.complete().elements, vec![(2, 8)]);
+    assert_eq!(
+        by_last.complete().elements,
+        vec![(1, 5, 9), (2, 4, 8), (3, 5, 7)]
+    );
+}
+
+#[test]
+fn changed_with_limit_trips_on_runaway_rule() {
+    let mut iteration = Iteration::new();
+    let numbers = iteration.variable::<u64>();
+    numbers.extend(vec![0]);
+
+    // This rule never reaches a fixpoint.
+    let error = loop {
+        match iteration.changed_with_limit(50) {
+            Ok(true) => numbers.from_map(&numbers, |&x| x + 1),
+            Ok(false) => panic!("reached a fixpoint"),
+            Err(error) => break error,
+        }
+    };
+    assert_eq!(error, crate::IterationError { limit: 50 });
+    assert_eq!(iteration.round(), 50);
+    assert_eq!(numbers.recent.borrow().elements, vec![49]);
+}
+
+#[test]
+fn changed_with_limit_allows_fixpoint() {
+    let mut iteration = Iteration::new();
+    let numbers = iteration.variable::<u64>();
+    numbers.extend(vec![0]);
+
+    // The first eleven rounds hold the numbers 0 to 10 in turn, and the
+    // twelfth finds the fixpoint.
+    while iteration.changed_with_limit(12).unwrap() {
+        numbers.from_map(&numbers, |&x| (x + 1).min(10));
+    }
+    assert_eq!(iteration.round(), 12);
+    assert_eq!(numbers.complete().len(), 11);
+}
+
+#[test]
+fn dedup_projection_collapses_provenance() {
+    // Reachability, recording the predecessor through which each node
+    // was first reached as its provenance.
+    let edges: Relation<(u32, u32)> = vec![(1, 2), (1, 3), (2, 4), (3, 4), (4, 5)].into();
+
+    let mut iteration = Iteration::new();
+    let reached = iteration.variable::<(u32, u32)>();
+    reached.dedup_projection(|&(node, _)| node);
+    reached.insert(vec![(1, 0)].into());
+
+    while iteration.changed() {
+        reached.from_join(&reached, &edges, |&node, _, &next| (next, node));
+    }
+
+    // Node 4 is reached through both 2 and 3, in the same round, and
+    // only the least tuple is kept.
+    let reached = reached.complete();
+    assert_eq!(
+        reached.elements,
+        vec![(1, 0), (2, 1), (3, 1), (4, 2), (5, 4)]
+    );
+}
+
+#[test]
+fn dedup_projection_against_stable_tuples() {
+    let mut iteration = Iteration::new();
+    let facts = iteration.variable::<(u32, char)>();
+    facts.dedup_projection(|&(fact, _)| fact);
+    facts.insert(vec![(1, 'b'), (2, 'b')].into());
+
+    let mut rounds = 0;
+    while iteration.changed() {
+        rounds += 1;
+        if rounds == 1 {
+            facts.insert(vec![(1, 'a'), (3, 'a')].into());
+        }
+    }
+
+    assert_eq!(
+        facts.complete().elements,
+        vec![(1, 'b'), (2, 'b'), (3, 'a')]
+    );
+}
+
+#[test]
+fn retain_one_per_key() {
+    let relation: Relation<(u32, &str)> = vec![
+        (1, "pear"),
+        (1, "apple"),
+        (1, "fig"),
+        (2, "kiwi"),
+        (3, "plum"),
+        (3, "date"),
+    ]
+    .into();
+
+    let smallest = relation.clone().retain_one_per_key(|a, b| a < b);
+    assert_eq!(
+        smallest.elements,
+        vec![(1, "apple"), (2, "kiwi"), (3, "date")]
+    );
+
+    // Ties between "date" and "plum" keep the smaller.
+    let shortest = relation.retain_one_per_key(|a, b| a.len() < b.len());
+    assert_eq!(
+        shortest.elements,
+        vec![(1, "fig"), (2, "kiwi"), (3, "date")]
+    );
+}
+
+#[test]
+fn from_sorted_keeps_elements() {
+    let elements: Vec<(u32, u32)> = (0..100).map(|x| (x / 10, x % 10)).collect();
+    let relation = Relation::from_sorted(elements.clone());
+    assert_eq!(relation.elements, Relation::from_vec(elements).elements);
+}
+
+#[test]
+#[cfg(debug_assertions)]
+#[should_panic(expected = "not sorted and distinct")]
+fn from_sorted_rejects_unsorted_in_debug() {
+    Relation::from_sorted(vec![2, 1, 3]);
+}
+
+#[test]
+#[cfg(debug_assertions)]
+#[should_panic(expected = "not sorted and distinct")]
+fn from_sorted_rejects_duplicates_in_debug() {
+    Relation::from_sorted(vec![1, 2, 2]);
+}
+
+#[test]
+fn from_join_into_rekeyed_single_pass() {
+    use std::cell::Cell;
+
+    let mut iteration = Iteration::new();
+    let left = iteration.variable::<(u32, u32)>();
+    let right = iteration.variable::<(u32, u32)>();
+    let by_first = iteration.variable::<(u32, u32)>();
+    let by_second = iteration.variable::<(u32, u32)>();
+    let expected = iteration.variable::<(u32, u32)>();
+    left.extend(vec![(1, 10), (1, 11), (2, 20), (3, 30)]);
+    right.extend(vec![(1, 100), (2, 200), (2, 201), (4, 400)]);
+
+    let joins = Cell::new(0);
+    while iteration.changed() {
+        crate::from_join_into_rekeyed(
+            &left,
+            &right,
+            |_, &a, &b| {
+                joins.set(joins.get() + 1);
+                (a, b)
+            },
+            |_, &a, &b| (b, a),
+            &by_first,
+            &by_second,
+        );
+        expected.from_join(&left, &right, |_, &a, &b| (a, b));
+    }
+
+    let expected = expected.complete();
+    assert_eq!(joins.get(), expected.len());
+    assert_eq!(by_first.complete().elements, expected.elements);
+    assert_eq!(
+        by_second.complete().elements,
+        Relation::from_map(&expected, |&(a, b)| (b, a)).elements
+    );
+}
+
+#[cfg(feature = "rayon")]
+#[test]
+fn large_relations_sort_in_parallel() {
+    use rand::{Rng, SeedableRng};
+
+    let mut rng = rand::rngs::StdRng::seed_from_u64(0);
+    // The last input has long runs of duplicates, which span the chunks
+    // that are deduplicated on separate threads.
+    for &(len, range) in &[(0, 1000), (10, 1000), (250_000, 1000), (250_000, 10)] {
+        let elements: Vec<(u32, u32)> = (0..len)
+            .map(|_| (rng.gen_range(0, range), rng.gen_range(0, range)))
+            .collect();
+        let mut expected = elements.clone();
+        expected.sort();
+        expected.dedup();
+
+        let (first, second) = elements.split_at(len / 2);
+        let merged = Relation::from_vec_par(first.to_vec())
+            .merge_par(Relation::from_vec_par(second.to_vec()));
+        assert_eq!(Relation::from_vec_par(elements).elements, expected);
+        assert_eq!(merged.elements, expected);
+    }
+}
+
+#[cfg(feature = "bench-support")]
+#[test]
+fn bench_generators_are_deterministic() {
+    use crate::bench;
+
+    let graph = bench::random_graph(100, 500, 7);
+    assert_eq!(graph.elements, bench::random_graph(100, 500, 7).elements);
+    assert_ne!(graph.elements, bench::random_graph(100, 500, 8).elements);
+    assert!(graph.iter().all(|&(a, b)| a < 100 && b < 100));
+
+    let grid = bench::grid_graph(3, 2);
+    assert_eq!(
+        grid.elements,
+        vec![(0, 1), (0, 3), (1, 2), (1, 4), (2, 5), (3, 4), (4, 5)]
+    );
+
+    let people = bench::people(200, 7);
+    let again = bench::people(200, 7);
+    assert_eq!(people.names.elements, again.names.elements);
+    assert_eq!(people.parents.elements, again.parents.elements);
+    assert_eq!(people.names.len(), 200);
+}
+
+/// Joins people's names, parents, and ages, as in the people example,
+/// with the inputs arriving over several rounds.
+fn people_by_join3(
+    rounds: &[Vec<(u32, u32, u32, u32)>],
+    ternary: bool,
+) -> Relation<(u32, u32, u32, u32)> {
+    let mut iteration = Iteration::new();
+    let names = iteration.variable::<(u32, u32)>();
+    let parents = iteration.variable::<(u32, u32)>();
+    let ages = iteration.variable::<(u32, u32)>();
+    let names_parents = iteration.variable::<(u32, (u32, u32))>();
+    let result = iteration.variable::<(u32, u32, u32, u32)>();
+
+    let mut round = 0;
+    while iteration.changed() || round < rounds.len() {
+        if let Some(facts) = rounds.get(round) {
+            names.extend(facts.iter().map(|&(a, name, _, _)| (a, name)));
+            parents.extend(facts.iter().map(|&(a, _, p, _)| (a, p)));
+            ages.extend(facts.iter().map(|&(a, _, _, age)| (a, age)));
+        }
+        round += 1;
+
+        if ternary {
+            result.from_join3(&names, &parents, &ages, |&a, &name, &p, &age| {
+                (a, name, p, age)
+            });
+        } else {
+            names_parents.from_join(&names, &parents, |&a, &name, &p| (a, (name, p)));
+            result.from_join(&names_parents, &ages, |&a, &(name, p), &age| {
+                (a, name, p, age)
+            });
+        }
+    }
+
+    result.complete()
+}
+
+#[test]
+fn join3_matches_chain_of_joins() {
+    let rounds = vec![
+        vec![(1, 0, 5, 10), (2, 1, 5, 30), (3, 0, 6, 10)],
+        vec![(1, 1, 6, 11), (4, 2, 7, 40)],
+        vec![],
+        vec![(2, 1, 7, 31), (5, 3, 1, 50), (1, 0, 5, 12)],
+    ];
+
+    let ternary = people_by_join3(&rounds, true);
+    assert_eq!(ternary.elements, people_by_join3(&rounds, false).elements);
+    assert_eq!(ternary.len(), 2 * 2 * 3 + 2 * 2 + 1 + 1 + 1);
+}
+
+#[test]
+fn keys_with_multiple_values() {
+    let relation: Relation<(u32, char)> = vec![(1, 'a'), (1, 'b'), (2, 'c')].into();
+    assert_eq!(relation.keys_with_multiple_values().elements, vec![1]);
+
+    let relation: Relation<(u32, char)> =
+        vec![(1, 'a'), (1, 'b'), (1, 'c'), (2, 'c'), (3, 'd'), (3, 'e')].into();
+    assert_eq!(relation.keys_with_multiple_values().elements, vec![1, 3]);
+
+    let function: Relation<(u32, char)> = vec![(1, 'a'), (2, 'a')].into();
+    assert!(function.keys_with_multiple_values().is_empty());
+}
+
+#[test]
+fn extend_sorted_matches_extend() {
+    let mut iteration = Iteration::new();
+    let sorted = iteration.variable::<(u32, u32)>();
+    let unsorted = iteration.variable::<(u32, u32)>();
+    sorted.extend_sorted((0..10).filter_map(|x| if x % 3 != 0 { Some((x, x * x)) } else { None }));
+    unsorted.extend(
+        (0..10)
+            .rev()
+            .filter_map(|x| if x % 3 != 0 { Some((x, x * x)) } else { None }),
+    );
+
+    while iteration.changed() {}
+
+    assert_eq!(sorted.complete().elements, unsorted.complete().elements);
+}
+
+#[test]
+fn join_step_debug_single_step() {
+    let mut iteration = Iteration::new();
+    let left = iteration.variable::<(u32, u32)>();
+    let right = iteration.variable::<(u32, u32)>();
+    left.extend(vec![(1, 10), (2, 20), (3, 30)]);
+    right.extend(vec![(1, 100), (3, 300), (3, 301), (4, 400)]);
+
+    assert!(iteration.changed());
+    let step = left.join_step_debug(&right, |&k, &v1, &v2| (k, v1 + v2));
+    assert_eq!(step.elements, vec![(1, 110), (3, 330), (3, 331)]);
+
+    // Nothing was inserted, so the variables do not change.
+    assert!(!iteration.changed());
+    assert!(left.join_step_debug(&right, |&k, _, _| k).is_empty());
+}
+
+#[test]
+fn stable_and_recent_len() {
+    let mut iteration = Iteration::new();
+    let variable = iteration.variable::<u32>();
+    variable.extend(vec![1, 2, 3]);
+    assert_eq!((variable.stable_len(), variable.recent_len()), (0, 0));
+
+    assert!(iteration.changed());
+    assert_eq!((variable.stable_len(), variable.recent_len()), (0, 3));
+
+    variable.extend(vec![3, 4]);
+    assert!(iteration.changed());
+    assert_eq!((variable.stable_len(), variable.recent_len()), (3, 1));
+
+    assert!(!iteration.changed());
+    assert_eq!((variable.stable_len(), variable.recent_len()), (4, 0));
+}
+
+#[test]
+fn join_structs_on_named_fields() {
+    #[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+    struct Def {
+        block: u32,
+        local: u32,
+        point: u32,
+    }
+
+    #[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+    struct Use {
+        point: u32,
+        block: u32,
+        local: u32,
+    }
+
+    let defs: Relation<Def> = vec![
+        Def {
+            block: 1,
+            local: 1,
+            point: 10,
+        },
+        Def {
+            block: 1,
+            local: 2,
+            point: 11,
+        },
+        Def {
+            block: 2,
+            local: 1,
+            point: 20,
+        },
+    ]
+    .into();
+    let uses: Relation<Use> = vec![
+        Use {
+            point: 12,
+            block: 1,
+            local: 2,
+        },
+        Use {
+            point: 21,
+            block: 2,
+            local: 1,
+        },
+        Use {
+            point: 22,
+            block: 2,
+            local: 2,
+        },
+    ]
+    .into();
+
+    assert_eq!(crate::key!(Def, point)(&defs[0]), 10);
+
+    let defs_by_key = Relation::from_map(&defs, |d| (crate::key!(Def, block, local)(d), d.point));
+    let uses_by_key = Relation::from_map(&uses, |u| (crate::key!(Use, block, local)(u), u.point));
+    let def_use = Relation::from_join(&defs_by_key, &uses_by_key, |_, &def, &use_| (def, use_));
+    assert_eq!(def_use.elements, vec![(11, 12), (20, 21)]);
+
+    let mut iteration = Iteration::new();
+    let def_vars = iteration.variable::<Def>();
+    let use_vars = iteration.variable::<Use>();
+    let joined = iteration.variable::<(u32, u32)>();
+    def_vars.insert(defs);
+    use_vars.insert(uses);
+    while iteration.changed() {
+        joined.from_join_by_key(
+            &def_vars,
+            &use_vars,
+            crate::key!(Def, block, local),
+            crate::key!(Use, block, local),
+            |_, def, use_| (def.point, use_.point),
+        );
+    }
+    assert_eq!(joined.complete().elements, def_use.elements);
+}
+
+#[test]
+fn semijoin_complements_antijoin() {
+    let mut iteration = Iteration::new();
+    let input = iteration.variable::<(u32, u32)>();
+    let kept = iteration.variable::<(u32, u32)>();
+    let dropped = iteration.variable::<(u32, u32)>();
+    input.extend((0..20).map(|x| (x % 7, x)));
+
+    let filter: Relation<u32> = vec![0, 3, 4, 9].into();
+    while iteration.changed() {
+        kept.from_semijoin(&input, &filter, |&k, &v| (k, v));
+        dropped.from_antijoin(&input, &filter, |&k, &v| (k, v));
+    }
+
+    let (input, kept, dropped) = (input.complete(), kept.complete(), dropped.complete());
+    assert!(kept.iter().all(|(k, _)| filter.contains(k)));
+    assert!(kept.intersect(&dropped).is_empty());
+    assert_eq!(kept.merge(dropped).elements, input.elements);
+}
+
+#[test]
+fn changed_detailed_names_changing_variables() {
+    let mut iteration = Iteration::new();
+    crate::variables!(iteration;
+        edges: (u32, u32) = vec![(1, 2), (2, 3), (3, 4)],
+        reach: (u32, u32),
+        unused: u32,
+    );
+    let changed = |names: &[&str]| -> Vec<(String, bool)> {
+        ["edges", "reach", "unused"]
+            .iter()
+            .map(|name| (name.to_string(), names.contains(name)))
+            .collect()
+    };
+
+    assert_eq!(iteration.changed_detailed(), changed(&["edges"]));
+    reach.from_map(&edges, |&edge| edge);
+    assert_eq!(iteration.changed_detailed(), changed(&["reach"]));
+
+    let mut rounds = 0;
+    loop {
+        reach.from_join(&reach, &edges, |_, &a, &c| (c, a));
+        let detailed = iteration.changed_detailed();
+        if detailed == changed(&[]) {
+            break;
+        }
+        assert_eq!(detailed, changed(&["reach"]));
+        rounds += 1;
+    }
+    assert!(rounds > 0);
+    assert!(unused.complete().is_empty());
+}
+
+#[test]
+fn filter_anti_excludes_blacklisted_nodes() {
+    let edges: Relation<(u32, u32)> = vec![(1, 2), (1, 5), (2, 3), (3, 4), (5, 6), (6, 7)].into();
+    let blacklist: Relation<u32> = vec![5].into();
+
+    let mut iteration = Iteration::new();
+    let reached = iteration.variable::<(u32, u32)>();
+    let allowed = iteration.variable::<(u32, u32)>();
+    allowed.insert(vec![(1, 0)].into());
+
+    while iteration.changed() {
+        // Both variables hold (node, predecessor) pairs.
+        reached.from_join(&allowed, &edges, |&node, _, &next| (next, node));
+        allowed.filter_anti(&reached, &blacklist, |&(node, _)| node);
+    }
+
+    let nodes: Vec<u32> = allowed.complete().iter().map(|&(node, _)| node).collect();
+    assert_eq!(nodes, vec![1, 2, 3, 4]);
+}
+
+#[test]
+fn filter_anti_keyed_on_trailing_field() {
+    let blacklist: Relation<u32> = vec![1, 3].into();
+
+    let mut iteration = Iteration::new();
+    let input = iteration.variable::<(u32, u32)>();
+    let allowed = iteration.variable::<(u32, u32)>();
+    input.insert(vec![(1, 3), (1, 4), (2, 1), (2, 2), (3, 3), (4, 0)].into());
+
+    while iteration.changed() {
+        // The second fields go back from 4 to 1, and from 3 to 0.
+        allowed.filter_anti(&input, &blacklist, |&(_, b)| b);
+    }
+
+    assert_eq!(allowed.complete().elements, vec![(1, 4), (2, 2), (4, 0)]);
+}
+
+#[test]
+fn merge_resolving_keeps_higher_version() {
+    use std::cmp::Ordering;
+
+    /// A value for a key, ordered by the key alone.
+    #[derive(Clone, Debug, PartialEq, Eq)]
+    struct Versioned {
+        key: u32,
+        version: u32,
+        value: &'static str,
+    }
+
+    impl PartialOrd for Versioned {
+        fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+            Some(self.cmp(other))
+        }
+    }
+
+    impl Ord for Versioned {
+        fn cmp(&self, other: &Self) -> Ordering {
+            self.key.cmp(&other.key)
+        }
+    }
+
+    let v = |key, version, value| Versioned {
+        key,
+        version,
+        value,
+    };
+    let old: Relation<Versioned> = vec![v(1, 1, "a"), v(2, 3, "b"), v(4, 1, "d")].into();
+    let new: Relation<Versioned> = vec![v(1, 2, "A"), v(2, 2, "B"), v(3, 1, "c")].into();
+
+    let merged = old.merge_resolving(new, |x, y| {
+        if x.version >= y.version {
+            x.clone()
+        } else {
+            y.clone()
+        }
+    });
+    assert_eq!(
+        merged.elements,
+        vec![v(1, 2, "A"), v(2, 3, "b"), v(3, 1, "c"), v(4, 1, "d")]
+    );
+}
+
+#[test]
+fn from_join_by_trailing_field() {
+    let mut iteration = Iteration::new();
+    let left = iteration.variable::<(u32, u32)>();
+    let right = iteration.variable::<(u32, u32)>();
+    let joined = iteration.variable::<(u32, u32, u32)>();
+
+    // Sorted by their first fields, the second fields are out of order.
+    left.extend(vec![(1, 30), (2, 10), (3, 20), (4, 10)]);
+    right.extend(vec![(5, 20), (6, 10), (7, 40)]);
+
+    let mut round = 0;
+    while iteration.changed() {
+        if round == 0 {
+            right.extend(vec![(8, 30), (9, 10)]);
+        }
+        round += 1;
+        joined.from_join_by(
+            &left,
+            &right,
+            |(_, b)| b,
+            |(_, b)| b,
+            |&b, &(a, _), &(c, _)| (a, b, c),
+        );
+    }
+
+    assert_eq!(
+        joined.complete().elements,
+        vec![
+            (1, 30, 8),
+            (2, 10, 6),
+            (2, 10, 9),
+            (3, 20, 5),
+            (4, 10, 6),
+            (4, 10, 9)
+        ]
+    );
+}
+
+#[test]
+fn group_by_key() {
+    let empty: Relation<(u32, u32)> = vec![].into();
+    assert_eq!(empty.group_by(|(k, _)| k).count(), 0);
+
+    let same: Relation<(u32, u32)> = vec![(1, 1), (1, 2), (1, 3)].into();
+    let groups: Vec<_> = same.group_by(|(k, _)| k).collect();
+    assert_eq!(groups, vec![(&1, &[(1, 1), (1, 2), (1, 3)][..])]);
+
+    let mixed: Relation<(u32, u32)> = vec![(1, 1), (2, 1), (2, 2), (3, 5)].into();
+    let groups: Vec<_> = mixed
+        .group_by(|(k, _)| k)
+        .map(|(k, group)| (*k, group.len()))
+        .collect();
+    assert_eq!(groups, vec![(1, 1), (2, 2), (3, 1)]);
+}
+
+#[test]
+fn unroll_join_matches_full_iteration() {
+    let edges: Relation<(u32, u32)> = (0..20)
+        .map(|x| (x, x + 1))
+        .chain(vec![(20, 0), (5, 12)])
+        .collect();
+
+    let reach = |depth: Option<usize>| {
+        let mut iteration = Iteration::new();
+        let reach = iteration.variable::<(u32, u32)>();
+        reach.insert(vec![(0, 0), (7, 7)].into());
+
+        let mut first = true;
+        while iteration.changed() {
+            if let (Some(depth), true) = (depth, first) {
+                reach.unroll_join(&edges, depth, |_, &start, &next| (next, start));
+            }
+            first = false;
+            reach.from_join(&reach, &edges, |_, &start, &next| (next, start));
+        }
+        reach.complete()
+    };
+
+    let full = reach(None);
+    for &depth in &[0, 1, 3, 8, 30] {
+        assert_eq!(reach(Some(depth)).elements, full.elements);
+    }
+}
+
+#[test]
+fn tabulate_dense_keys() {
+    let relation: Relation<(usize, char)> = vec![(0, 'a'), (2, 'c')].into();
+    assert_eq!(relation.tabulate(3, '-'), vec!['a', '-', 'c']);
+    assert_eq!(relation.tabulate(5, '-'), vec!['a', '-', 'c', '-', '-']);
+}
+
+#[test]
+#[should_panic(expected = "key 2 is out of bounds for length 2")]
+fn tabulate_key_out_of_bounds() {
+    let relation: Relation<(usize, char)> = vec![(0, 'a'), (2, 'c')].into();
+    relation.tabulate(2, '-');
+}
+
+#[test]
+fn reduce_by_key() {
+    let relation: Relation<(char, u32)> =
+        vec![('a', 3), ('a', 1), ('a', 7), ('b', 2), ('c', 5), ('c', 4)].into();
+
+    let sums = relation.reduce_by(|(k, _)| k, || 0, |sum, &(_, v)| sum + v);
+    assert_eq!(sums.elements, vec![('a', 11), ('b', 2), ('c', 9)]);
+
+    let mins = relation.reduce_by(|(k, _)| k, || u32::MAX, |min, &(_, v)| min.min(v));
+    assert_eq!(mins.elements, vec![('a', 1), ('b', 2), ('c', 4)]);
+
+    let counts = relation.reduce_by(|(k, _)| k, || 0, |count, _| count + 1);
+    assert_eq!(counts.elements, vec![('a', 3), ('b', 1), ('c', 2)]);
+}
+
+/// Counts the allocations made by each thread, so that tests can check
+/// how many an operation makes.
+struct CountingAllocator;
+
+std::thread_local! {
+    static ALLOCATIONS: std::cell::Cell<usize> = const { std::cell::Cell::new(0) };
+}
+
+unsafe impl std::alloc::GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: std::alloc::Layout) -> *mut u8 {
+        ALLOCATIONS.with(|count| count.set(count.get() + 1));
+        std::alloc::System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: std::alloc::Layout) {
+        std::alloc::System.dealloc(ptr, layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: std::alloc::Layout, size: usize) -> *mut u8 {
+        ALLOCATIONS.with(|count| count.set(count.get() + 1));
+        std::alloc::System.realloc(ptr, layout, size)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+/// Returns the result of `f`, and the number of allocations and
+/// reallocations it made.
+fn count_allocations<T>(f: impl FnOnce() -> T) -> (T, usize) {
+    let before = ALLOCATIONS.with(|count| count.get());
+    let result = f();
+    (result, ALLOCATIONS.with(|count| count.get()) - before)
+}
+
+#[test]
+fn variable_with_capacity_is_only_a_hint() {
+    // Walks along a chain of 1000 edges, adding one node each round, in
+    // order, so that batches are appended to the first stable batch.
+    let edges: Relation<(u32, u32)> = (0..1000).map(|x| (x, x + 1)).collect();
+    let walk = |capacity| {
+        let mut iteration = Iteration::new();
+        let reached = iteration.variable_with_capacity::<(u32, ())>("reached", capacity);
+        reached.insert(vec![(0, ())].into());
+        assert_eq!(reached.recent.borrow().elements.capacity(), 0);
+        while iteration.changed() {
+            reached.from_join(&reached, &edges, |_, &(), &b| (b, ()));
+        }
+        reached.complete()
+    };
+
+    // Without the hint, the first stable batch grows as it is appended
+    // to; with it, it never needs to.
+    let (plain, plain_allocations) = count_allocations(|| walk(0));
+    let (reserved, reserved_allocations) = count_allocations(|| walk(1024));
+    assert_eq!(reserved.elements, plain.elements);
+    assert!(reserved_allocations < plain_allocations);
+}
+
+#[test]
+fn map_renames_after_fixpoint() {
+    let mut iteration = Iteration::new();
+    let edges = iteration.variable::<(u32, u32)>();
+    edges.extend(vec![(1, 2), (1, 3), (3, 1)]);
+    while iteration.changed() {}
+
+    // Renaming node 3 to 2 turns two edges into one.
+    let renamed = edges.complete().map(|&(a, b)| (a.min(2), b.min(2)));
+    assert_eq!(renamed.elements, vec![(1, 2), (2, 1)]);
+}
+
+#[test]
+fn join_with_matches_variable_join() {
+    let left: Relation<(u32, u32)> = vec![(1, 10), (1, 11), (2, 20), (4, 40)].into();
+    let right: Relation<(u32, u32)> = vec![(1, 100), (2, 200), (2, 201), (3, 300)].into();
+
+    let joined = left.join_with(&right, |&k, &v1, &v2| (k, v1, v2));
+
+    let mut iteration = Iteration::new();
+    let variable = iteration.variable::<(u32, u32)>();
+    let output = iteration.variable::<(u32, u32, u32)>();
+    variable.insert(left.clone());
+    while iteration.changed() {
+        output.from_join(&variable, &right, |&k, &v1, &v2| (k, v1, v2));
+    }
+
+    assert_eq!(joined.elements, output.complete().elements);
+    assert_eq!(joined.len(), 4);
+}
+
+#[test]
+fn extend_sorted_suffix_in_batches() {
+    let batches: Vec<Vec<(u32, u32)>> = vec![
+        vec![(3, 1), (1, 2)],
+        vec![(2, 2), (1, 2), (0, 9)],
+        vec![],
+        vec![(5, 5), (4, 4)],
+    ];
+
+    let mut relation: Relation<(u32, u32)> = Relation::from_vec(vec![]);
+    for batch in batches.iter() {
+        relation.extend_sorted_suffix(batch.clone());
+    }
+
+    assert_eq!(
+        relation.elements,
+        Relation::from_iter(batches.into_iter().flatten()).elements
+    );
+}
+
+#[test]
+fn complete_into_reuses_buffer() {
+    let mut buffer = Vec::with_capacity(64);
+    for n in 1..5 {
+        let mut iteration = Iteration::new();
+        let edges: Relation<(u32, u32)> = (0..n).map(|x| (x, x + 1)).collect();
+        let reach = iteration.variable::<(u32, u32)>();
+        reach.insert(edges.clone());
+        while iteration.changed() {
+            reach.from_join(&reach, &edges, |_, &a, &c| (c, a));
+        }
+
+        let expected = reach.complete_shared();
+        reach.complete_into(&mut buffer);
+        assert_eq!(buffer, expected.elements);
+    }
+    assert!(buffer.capacity() >= 64);
+}
+
+#[test]
+fn from_join_tuple_matches_closure() {
+    let mut iteration = Iteration::new();
+    let names = iteration.variable::<(u32, &str)>();
+    let ages = iteration.variable::<(u32, u32)>();
+    let natural = iteration.variable::<(u32, &str, u32)>();
+    let manual = iteration.variable::<(u32, &str, u32)>();
+    names.extend(vec![(1, "ada"), (2, "bob"), (2, "rob"), (3, "cy")]);
+    ages.extend(vec![(1, 36), (2, 41), (4, 7)]);
+
+    while iteration.changed() {
+        natural.from_join_tuple(&names, &ages);
+        manual.from_join(&names, &ages, |&id, &name, &age| (id, name, age));
+    }
+
+    let natural = natural.complete();
+    assert_eq!(natural.elements, manual.complete().elements);
+    assert_eq!(
+        natural.elements,
+        vec![(1, "ada", 36), (2, "bob", 41), (2, "rob", 41)]
+    );
+}
+
+#[test]
+fn merge_factor_changes_batches_not_results() {
+    use crate::IterationConfig;
+
+    let reach = |merge_factor| {
+        let mut iteration = Iteration::with_config(IterationConfig { merge_factor });
+        let edges: Relation<(u32, u32)> = (0..40).map(|x| (x, x + 1)).collect();
+        let reach = iteration.variable::<(u32, u32)>();
+        reach.insert(edges.clone());
+        let mut max_batches = 0;
+        while iteration.changed() {
+            max_batches = max_batches.max(reach.stable.borrow().len());
+            reach.from_join(&reach, &edges, |_, &a, &c| (c, a));
+        }
+        (max_batches, reach.complete())
+    };
+
+    let (default_batches, expected) = reach(IterationConfig::default().merge_factor);
+    for &merge_factor in &[0, 1, 4, 8] {
+        let (batches, result) = reach(merge_factor);
+        assert_eq!(result.elements, expected.elements);
+        if merge_factor == 0 {
+            assert!(batches > default_batches);
+        }
+    }
+}
+
+#[test]
+fn relation_antijoin() {
+    // The relation of the `from_antijoin` example, but complete.
+    let pairs: Relation<(usize, usize)> = (0..10).map(|x| (x, x + 1)).collect();
+    let relation: Relation<usize> = (0..10).filter(|x| x % 3 == 0).collect();
+
+    let kept = pairs.antijoin(&relation);
+    assert_eq!(kept.len(), 6);
+    assert!(kept.iter().all(|(key, _)| key % 3 != 0));
+    assert_eq!(
+        kept.elements,
+        Relation::from_antijoin(&pairs, &relation, |&k, &v| (k, v)).elements
+    );
+
+    assert_eq!(
+        pairs.antijoin(&Relation::from_vec(vec![])).elements,
+        pairs.elements
+    );
+}
+
+#[cfg(feature = "trace")]
+#[test]
+fn trace_logs_each_round() {
+    use std::sync::Mutex;
+
+    struct Collect(Mutex<Vec<String>>);
+
+    impl log::Log for Collect {
+        fn enabled(&self, metadata: &log::Metadata) -> bool {
+            metadata.target() == "datafrog"
+        }
+
+        fn log(&self, record: &log::Record) {
+            if self.enabled(record.metadata()) {
+                self.0.lock().unwrap().push(record.args().to_string());
+            }
+        }
+
+        fn flush(&self) {}
+    }
+
+    static LOGGER: Collect = Collect(Mutex::new(Vec::new()));
+    log::set_logger(&LOGGER).unwrap();
+    log::set_max_level(log::LevelFilter::Trace);
+
+    let mut iteration = Iteration::new();
+    let numbers = iteration.variable_named::<u32>("numbers");
+    numbers.extend(vec![1, 2]);
+
+    iteration.set_trace(true);
+    assert!(iteration.changed());
+    iteration.set_trace(false);
+    assert!(!iteration.changed());
+
+    let lines = LOGGER.0.lock().unwrap();
+    assert_eq!(
+        *lines,
+        vec![r#"round 1: variable "numbers" has 2 recent and 0 stable tuples"#]
+    );
+}
+
+#[test]
+fn last_per_key() {
+    let empty: Relation<(u32, u32)> = vec![].into();
+    assert!(empty.last_per_key(|(k, _)| k).is_empty());
+
+    let single: Relation<(u32, u32)> = vec![(4, 2)].into();
+    assert_eq!(single.last_per_key(|(k, _)| k).elements, vec![(4, 2)]);
+
+    let many: Relation<(u32, u32)> = vec![(1, 5), (1, 9), (1, 7), (2, 3), (3, 1), (3, 0)].into();
+    assert_eq!(
+        many.last_per_key(|(k, _)| k).elements,
+        vec![(1, 9), (2, 3), (3, 1)]
+    );
+}
+
+#[test]
+fn datalog_joins_on_the_common_argument() {
+    let mut iteration = Iteration::new();
+    crate::datalog! {
+        iteration;
+        variables {
+            in1: (u32, u32) = vec![(1, 2)],
+            in2: (u32, u32) = vec![(2, 3), (1, 9)],
+            first_first: (u32, u32),
+            last_first: (u32, u32),
+            first_last: (u32, u32),
+            last_last: (u32, u32),
+        }
+        rules {
+            first_first(b, c) <- in1(a, b), in2(a, c);
+            last_first(a, c) <- in1(a, b), in2(b, c);
+            first_last(b, c) <- in2(a, b), in1(c, a);
+            last_last(a, c) <- in1(a, b), in1(c, b);
+        }
+    }
+
+    assert_eq!(first_first.complete().elements, vec![(2, 9)]);
+    assert_eq!(last_first.complete().elements, vec![(1, 3)]);
+    assert_eq!(first_last.complete().elements, vec![(3, 1)]);
+    assert_eq!(last_last.complete().elements, vec![(1, 1)]);
+}
+
+#[test]
+fn datalog_matches_people_query() {
+    // Person, name, and parent ids, as in the people example.
+    let names = vec![(1, 10), (2, 10), (3, 11), (4, 11), (5, 10)];
+    let parents = vec![(1, 2), (1, 3), (3, 4), (5, 1), (5, 4)];
+
+    let mut iteration = Iteration::new();
+    crate::datalog! {
+        iteration;
+        variables {
+            has_name: (u32, u32) = names.clone(),
+            has_parent: (u32, u32) = parents.clone(),
+            parent_names: ((u32, u32), u32),
+            name_pairs: ((u32, u32), ()),
+            query: (u32, u32, u32),
+        }
+        rules {
+            parent_names((p, name), a) <- has_name(a, name), has_parent(a, p);
+            name_pairs((p, name), ()) <- has_name(p, name);
+            query(a, p, name) <- parent_names((p, name), a), name_pairs((p, name), ());
+        }
+    }
+
+    // query(a, p, name) <- has_name(a, name), has_parent(a, p), has_name(p, name)
+    let names: Relation<(u32, u32)> = names.into();
+    let manual: Relation<(u32, u32, u32)> = parents
+        .iter()
+        .filter_map(|&(a, p)| {
+            let name = names.iter().find(|&&(x, _)| x == a)?.1;
+            names.contains(&(p, name)).then_some((a, p, name))
+        })
+        .collect();
+
+    let query = query.complete();
+    assert_eq!(query.elements, manual.elements);
+    assert_eq!(query.elements, vec![(1, 2, 10), (3, 4, 11), (5, 1, 10)]);
+}
+
+#[test]
+fn insert_ref_seeds_several_variables() {
+    let base: Relation<(u32, u32)> = vec![(1, 2), (2, 3)].into();
+
+    let mut iteration = Iteration::new();
+    let forward = iteration.variable::<(u32, u32)>();
+    let backward = iteration.variable::<(u32, u32)>();
+    let both = iteration.variable::<(u32, u32)>();
+    forward.insert_ref(&base);
+    backward.insert_ref(&base);
+    both.insert_ref(&base);
+
+    while iteration.changed() {
+        backward.from_map(&forward, |&(a, b)| (b, a));
+        both.from_map(&backward, |&edge| edge);
+    }
+
+    assert_eq!(forward.complete().elements, base.elements);
+    assert_eq!(
+        backward.complete().elements,
+        vec![(1, 2), (2, 1), (2, 3), (3, 2)]
+    );
+    assert_eq!(both.complete().len(), 4);
+}
+
+#[test]
+fn is_sorted_and_distinct_checks_manual_relations() {
+    let valid: Relation<u32> = vec![3, 1, 2].into();
+    assert!(valid.is_sorted_and_distinct());
+    valid.assert_valid();
+
+    let unsorted = Relation {
+        elements: vec![1, 3, 2],
+    };
+    let duplicated = Relation {
+        elements: vec![1, 2, 2],
+    };
+    assert!(!unsorted.is_sorted_and_distinct());
+    assert!(!duplicated.is_sorted_and_distinct());
+}
+
+#[test]
+#[should_panic(expected = "3 at index 1 is followed by 2")]
+fn assert_valid_names_first_offending_pair() {
+    Relation {
+        elements: vec![1, 3, 2, 0],
+    }
+    .assert_valid();
+}
+
+#[test]
+fn from_join_exists_emits_once_per_key() {
+    let mut iteration = Iteration::new();
+    let left = iteration.variable::<(u32, u32)>();
+    let right: Relation<(u32, u32)> = (0..100)
+        .map(|v| (1, v))
+        .chain(vec![(2, 5), (3, 7)])
+        .collect();
+    let matched = iteration.variable::<(u32, u32, u32)>();
+    let keys = iteration.variable::<u32>();
+
+    left.extend((0..100).map(|v| (1, v)).chain(vec![(3, 9), (4, 0)]));
+
+    while iteration.changed() {
+        matched.from_join_exists(&left, &right, |&k, &v1, &v2| (k, v1, v2));
+        keys.from_join_exists(&left, &right, |&k, _, _| k);
+    }
+
+    assert_eq!(matched.complete().elements, vec![(1, 0, 0), (3, 9, 7)]);
+    assert_eq!(keys.complete().elements, vec![1, 3]);
+}
+
+#[test]
+fn from_join_exists_emits_once_per_key_across_rounds() {
+    let steps: Relation<(u32, u32)> = (0..4).map(|v| (v, v + 1)).collect();
+    let calls = std::cell::Cell::new(0);
+
+    let mut iteration = Iteration::new();
+    let counter = iteration.variable::<(u32, ())>();
+    let left = iteration.variable::<(u32, u32)>();
+    let right = iteration.variable::<(u32, u32)>();
+    let matched = iteration.variable::<(u32, u32, u32)>();
+    counter.insert(vec![(0, ())].into());
+
+    // One value arrives on each side per round. Key 2 first matches
+    // within the recent batches, in round 1, and key 1 first matches a
+    // stable value on the left, in round 3; both keep gaining values.
+    while iteration.changed() {
+        counter.from_join(&counter, &steps, |_, &(), &v| (v, ()));
+        left.from_map(&counter, |&(v, ())| (v % 2 + 1, v));
+        right.from_map(&counter, |&(v, ())| (2 - v / 3, v));
+        matched.from_join_exists(&left, &right, |&k, &v1, &v2| {
+            calls.set(calls.get() + 1);
+            (k, v1, v2)
+        });
+    }
+
+    assert_eq!(matched.complete().elements, vec![(1, 0, 3), (2, 1, 1)]);
+    assert_eq!(calls.get(), 2);
+}
+
+#[test]
+fn map_monotone_offsets_node_ids() {
+    let edges: Relation<(u32, u32)> = vec![(2, 3), (1, 2), (1, 5)].into();
+    let shifted = edges.map_monotone(|(a, b)| (a + 100, b + 100));
+    assert_eq!(shifted.elements, vec![(101, 102), (101, 105), (102, 103)]);
+}
+
+#[test]
+#[cfg(debug_assertions)]
+#[should_panic(expected = "does not preserve the order")]
+fn map_monotone_rejects_reordering_transforms() {
+    let edges: Relation<(u32, u32)> = vec![(1, 2), (2, 1)].into();
+    edges.map_monotone(|(a, b)| (b, a));
+}
+
+#[test]
+fn step_reports_new_tuples_per_variable() {
+    let mut iteration = Iteration::new();
+    let edges = iteration.variable_named::<(u32, u32)>("edges");
+    let reach = iteration.variable_named::<(u32, u32)>("reach");
+    edges.extend(vec![(1, 2), (2, 3), (3, 4)]);
+    // reach(B, A) means that B is reachable from A.
+    reach.extend(vec![(2, 1), (3, 2), (4, 3)]);
+
+    let mut reports = Vec::new();
+    loop {
+        let report = iteration.step();
+        if !report.changed() {
+            break;
+        }
+        reach.from_join(&reach, &edges, |_, &a, &c| (c, a));
+        reports.push(report);
+    }
+
+    let totals: Vec<usize> = reports.iter().map(|report| report.new_tuples).collect();
+    assert_eq!(totals, vec![6, 2, 1]);
+    assert_eq!(reports[1].round, 2);
+    assert_eq!(reports[1].variables[0].name, "edges");
+    assert!(!reports[1].variables[0].changed);
+    assert_eq!(reports[1].variables[1].new_tuples, 2);
+}
+
+#[test]
+fn partition_splits_edges_by_type() {
+    // (type, source, target)
+    let edges: Relation<(u32, u32, u32)> = vec![(0, 1, 2), (2, 3, 4), (0, 2, 3), (2, 1, 1)].into();
+    let parts = edges.partition(3, |&(kind, _, _)| kind as usize);
+
+    assert_eq!(parts.len(), 3);
+    assert_eq!(parts[0].elements, vec![(0, 1, 2), (0, 2, 3)]);
+    assert!(parts[1].is_empty());
+    assert_eq!(parts[2].elements, vec![(2, 1, 1), (2, 3, 4)]);
+}
+
+#[test]
+#[should_panic(expected = "partition needs at least one bucket")]
+fn partition_into_no_buckets_panics() {
+    let numbers: Relation<u32> = vec![1, 2].into();
+    numbers.partition(0, |_| 0);
+}
+
+#[test]
+fn new_stratum_completes_before_negation() {
+    use crate::IterationConfig;
+
+    let edges: Relation<(u32, u32)> = vec![(1, 2), (2, 3), (3, 1), (4, 1)].into();
+
+    let mut iteration = Iteration::with_config(IterationConfig { merge_factor: 0 });
+
+    // Nodes on a cycle, computed to a fixpoint in their own stratum.
+    let cyclic = {
+        let mut stratum = iteration.new_stratum();
+        // reach(B, A) means that B is reachable from A.
+        let reach = stratum.variable::<(u32, u32)>();
+        reach.extend(edges.iter().map(|&(a, b)| (b, a)));
+        while stratum.changed() {
+            reach.from_join(&reach, &edges, |_, &a, &c| (c, a));
+        }
+        let reach = reach.complete();
+        Relation::from_iter(reach.iter().filter(|(b, a)| a == b).map(|&(n, _)| n))
+    };
+    assert_eq!(cyclic.elements, vec![1, 2, 3]);
+
+    let sources = iteration.variable::<(u32, u32)>();
+    let acyclic_sources = iteration.variable::<u32>();
+    sources.insert(edges.clone());
+    while iteration.changed() {
+        acyclic_sources.from_antijoin(&sources, &cyclic, |&a, _| a);
+    }
+
+    assert_eq!(acyclic_sources.complete().elements, vec![4]);
+}
+
+#[test]
+#[should_panic(expected = "has not reached a fixpoint")]
+fn new_stratum_must_reach_fixpoint_first() {
+    let edges: Relation<(u32, u32)> = vec![(1, 2), (2, 3)].into();
+
+    let mut iteration = Iteration::new();
+    let mut stratum = iteration.new_stratum();
+    let reach = stratum.variable::<(u32, u32)>();
+    reach.extend(edges.iter().map(|&(a, b)| (b, a)));
+    assert!(stratum.changed());
+
+    iteration.changed();
+}
+
+#[test]
+fn with_capacity_reserves_space() {
+    let relation = Relation::<(u32, u32)>::with_capacity(64);
+    assert!(relation.is_empty());
+    assert!(relation.capacity() >= 64);
+
+    let merged = relation.merge(vec![(1, 2), (3, 4)].into());
+    assert_eq!(merged.len(), 2);
+    assert!(merged.capacity() >= 2);
+}
+
+/// Like `triangles_via_leapjoin`, but with the values of `b` and `c`
+/// looked up in a `RelationIndex`.
+fn triangles_via_indexed_leapjoin(
+    a: &Relation<(u32, u32)>,
+    b: &Relation<(u32, u32)>,
+    c: &Relation<(u32, u32)>,
+) -> Relation<(u32, u32, u32)> {
+    let (b, c) = (b.index(), c.index());
+    Relation::from_leapjoin(
+        a,
+        (
+            b.extend_with(|&(_, y)| y),
+            c.extend_with(|&(x, _)| x),
+            crate::ValueFilter::from(|&(x, _), &z| x != z),
+        ),
+        |&(x, y), &z| (x, y, z),
+    )
+}
+
+proptest! {
+    #[test]
+    fn triangles_indexed_leapjoin_vs_leapjoin((a, b, c) in (inputs(), inputs(), inputs())) {
+        let (a, b, c): (Relation<_>, Relation<_>, Relation<_>) = (a.into(), b.into(), c.into());
+        let triangles1 = triangles_via_leapjoin(&a, &b, &c);
+        let triangles2 = triangles_via_indexed_leapjoin(&a, &b, &c);
+        assert_eq!(triangles1.elements, triangles2.elements);
+    }
+}
+
+#[test]
+fn relation_index_looks_up_sparse_keys() {
+    let relation: Relation<(u64, u32)> = vec![(1 << 40, 2), (3, 9), (1 << 40, 1)].into();
+    let index = relation.index();
+
+    assert_eq!(index.len(), 2);
+    assert_eq!(index.get(&(1 << 40)), &[1, 2]);
+    assert_eq!(index.get(&3), &[9]);
+    assert!(index.get(&4).is_empty());
+
+    let source: Relation<(u64, ())> = vec![(3, ()), (1 << 40, ()), (5, ())].into();
+    let extended =
+        Relation::from_leapjoin(&source, index.extend_with(|&(k, ())| k), |&(k, ()), &v| {
+            (k, v)
+        });
+    assert_eq!(extended.elements, vec![(3, 9), (1 << 40, 1), (1 << 40, 2)]);
+}
+
+#[test]
+fn from_product_combines_each_pair_once() {
+    let mut iteration = Iteration::new();
+    let sizes = iteration.variable::<u32>();
+    let colors = iteration.variable::<char>();
+    let combinations = iteration.variable::<(u32, char)>();
+    let mut rounds = 0;
+
+    sizes.extend(vec![1, 2]);
+    colors.extend(vec!['r']);
+    while iteration.changed() {
+        rounds += 1;
+        // New tuples arrive in later rounds, and must still be combined
+        // with the earlier ones.
+        if rounds == 2 {
+            sizes.extend(vec![3]);
+            colors.extend(vec!['g']);
+        }
+        combinations.from_product(&sizes, &colors, |&size, &color| (size, color));
+    }
+
+    let combinations = combinations.complete();
+    assert_eq!(combinations.len(), 6);
+    assert!(combinations.contains(&(3, 'g')));
+    assert!(combinations.contains(&(1, 'g')));
+    assert!(combinations.contains(&(3, 'r')));
+}
+
+#[test]
+fn complete_is_deterministic() {
+    fn run(edges: &[(u32, u32)], merge_factor: usize) -> Vec<(u32, u32)> {
+        use crate::IterationConfig;
+
+        let edges: Relation<(u32, u32)> = edges.iter().collect();
+        let mut iteration = Iteration::with_config(IterationConfig { merge_factor });
+        // reach(B, A) means that B is reachable from A.
+        let reach = iteration.variable::<(u32, u32)>();
+        reach.extend(edges.iter().map(|&(a, b)| (b, a)));
+        while iteration.changed() {
+            reach.from_join(&reach, &edges, |_, &a, &c| (c, a));
+        }
+        reach.complete().elements
+    }
+
+    let edges: Vec<(u32, u32)> = (0..50).map(|x| (x * 7 % 50, x * 13 % 50)).collect();
+    let mut reversed = edges.clone();
+    reversed.reverse();
+
+    let result = run(&edges, 2);
+    assert!(Relation {
+        elements: result.clone()
+    }
+    .is_sorted_and_distinct());
+    assert_eq!(run(&edges, 2), result);
+    assert_eq!(run(&reversed, 2), result);
+    assert_eq!(run(&edges, 0), result);
+}
+
+#[test]
+fn insert_antijoin_matches_antijoin_in_loop() {
+    let input: Relation<(u32, u32)> = (0..20).map(|x| (x % 7, x)).collect();
+    let anti: Relation<u32> = vec![1, 4, 5, 9].into();
+
+    let mut iteration = Iteration::new();
+    let seeded = iteration.variable::<(u32, u32)>();
+    let source = iteration.variable::<(u32, u32)>();
+    let looped = iteration.variable::<(u32, u32)>();
+    seeded.insert_antijoin(&input, &anti, |&k, &v| (v, k));
+    source.insert(input.clone());
+    while iteration.changed() {
+        looped.from_antijoin(&source, &anti, |&k, &v| (v, k));
+    }
+
+    let seeded = seeded.complete();
+    assert_eq!(seeded.elements, looped.complete().elements);
+    assert_eq!(seeded.len(), 11);
+}
+
+#[test]
+fn gallop_index_handles_huge_lengths() {
+    use crate::join::gallop_index;
+
+    // No slice of these lengths exists; only the index arithmetic runs.
+    let lens = [
+        usize::MAX,
+        usize::MAX - 1,
+        usize::MAX / 2 + 1,
+        isize::MAX as usize,
+    ];
+    for &len in lens.iter() {
+        for &prefix in [0, 1, 2, 1000, len / 2, len / 2 + 3, len - 1, len].iter() {
+            let mut calls = 0;
+            let count = gallop_index(len, |index| {
+                assert!(index < len);
+                calls += 1;
+                index < prefix
+            });
+            assert_eq!(count, prefix);
+            assert!(calls <= 2 * usize::BITS as usize + 2);
+        }
+    }
+}
+
+proptest! {
+    #[test]
+    fn gallop_index_counts_prefix(len in 0_usize..2000, prefix in 0_usize..2000) {
+        let prefix = prefix.min(len);
+        assert_eq!(crate::join::gallop_index(len, |index| index < prefix), prefix);
+    }
+}
+
+#[test]
+fn key_range_indexes_side_tables() {
+    let relation: Relation<(u32, u32)> = vec![(1, 10), (3, 30), (3, 31), (7, 70)].into();
+    let weights = [0.5, 1.0, 2.0, 4.0];
+
+    let range = relation.key_range(&3, |(k, _)| k);
+    assert_eq!(range, 1..3);
+    assert_eq!(weights[range].iter().sum::<f64>(), 3.0);
+
+    assert_eq!(relation.key_range(&0, |(k, _)| k), 0..0);
+    assert_eq!(relation.key_range(&5, |(k, _)| k), 3..3);
+    assert_eq!(relation.key_range(&9, |(k, _)| k), 4..4);
+}
+
+#[test]
+fn variable_from_snapshot_only_derives_new_consequences() {
+    type Snapshot = crate::RelationSnapshot<(u32, u32)>;
+
+    fn reachability(
+        edges: &[(u32, u32)],
+        snapshots: Option<(Snapshot, Snapshot)>,
+    ) -> (Snapshot, Snapshot, usize) {
+        let mut iteration = Iteration::new();
+        let (edge, reach) = match snapshots {
+            Some((edge, reach)) => (
+                iteration.variable_from_snapshot("edge", edge),
+                iteration.variable_from_snapshot("reach", reach),
+            ),
+            None => (
+                iteration.variable_named("edge"),
+                iteration.variable_named("reach"),
+            ),
+        };
+        edge.extend(edges.iter().cloned());
+        // reach(B, A) means that B is reachable from A.
+        reach.extend(edges.iter().map(|&(a, b)| (b, a)));
+
+        let mut recent = 0;
+        loop {
+            let report = iteration.step();
+            if !report.changed() {
+                break;
+            }
+            recent += report.new_tuples;
+            reach.from_join(&reach, &edge, |_, &a, &c| (c, a));
+        }
+        (edge.snapshot(), reach.snapshot(), recent)
+    }
+
+    let edges: Vec<(u32, u32)> = (0..20).map(|x| (x, x + 1)).collect();
+    let (edge, reach, first) = reachability(&edges, None);
+    assert_eq!(reach.relation().len(), 210);
+
+    // Extending the chain by one edge only derives the pairs that reach
+    // its new node.
+    let (_, incremental, second) = reachability(&[(20, 21)], Some((edge, reach)));
+    let mut all_edges = edges.clone();
+    all_edges.push((20, 21));
+    let (_, scratch, _) = reachability(&all_edges, None);
+
+    assert_eq!(incremental.relation().elements, scratch.relation().elements);
+    assert_eq!(first, 230);
+    assert_eq!(second, 1 + 21);
+}
+
+proptest! {
+    #[test]
+    fn from_join_dedup_matches_from_join(edges in inputs()) {
+        // Each pair of nodes with a common predecessor, many times over.
+        let mut iteration = Iteration::new();
+        let edges_var = iteration.variable::<(u32, u32)>();
+        let plain = iteration.variable::<(u32, u32)>();
+        let deduped = iteration.variable::<(u32, u32)>();
+        edges_var.extend(edges.iter().cloned());
+        while iteration.changed() {
+            plain.from_join(&edges_var, &edges_var, |_, &b, &c| (b % 8, c % 8));
+            deduped.from_join_dedup(&edges_var, &edges_var, |_, &b, &c| (b % 8, c % 8));
+        }
+        assert_eq!(plain.complete().elements, deduped.complete().elements);
+    }
+}
+
+#[test]
+fn from_join_dedup_on_complete_graph() {
+    let nodes = 100;
+    let mut iteration = Iteration::new();
+    let edges = iteration.variable::<(u32, u32)>();
+    let collapsed = iteration.variable::<(u32, u32)>();
+    edges.extend((0..nodes).flat_map(|a| (0..nodes).map(move |b| (a, b))));
+    while iteration.changed() {
+        collapsed.from_join_dedup(&edges, &edges, |_, &b, &c| (b % 2, c % 2));
+    }
+    assert_eq!(
+        collapsed.complete().elements,
+        vec![(0, 0), (0, 1), (1, 0), (1, 1)]
+    );
+}
+
+#[test]
+fn values_for_looks_up_each_key() {
+    let relation: Relation<(u32, char)> = vec![(2, 'b'), (1, 'z'), (2, 'a'), (4, 'q')].into();
+
+    assert_eq!(relation.values_for(&2).cloned().collect::<String>(), "ab");
+    assert_eq!(relation.values_for(&1).cloned().collect::<String>(), "z");
+    assert_eq!(relation.values_for(&4).cloned().collect::<String>(), "q");
+    assert_eq!(relation.values_for(&0).count(), 0);
+    assert_eq!(relation.values_for(&3).count(), 0);
+}
+
+#[test]
+fn reset_reuses_variables_for_new_queries() {
+    let edges: Relation<(u32, u32)> = vec![(1, 2), (2, 3), (3, 4), (5, 6)].into();
+
+    let mut iteration = Iteration::new();
+    // reached(B, A) means that B is reachable from the start A.
+    let reached = iteration.variable::<(u32, u32)>();
+
+    let mut counts = Vec::new();
+    for start in 1..=5 {
+        iteration.reset();
+        assert_eq!(iteration.round(), 0);
+        reached.extend(vec![(start, start)]);
+        while iteration.changed() {
+            reached.from_join(&reached, &edges, |_, &start, &next| (next, start));
+        }
+        counts.push(reached.stable_len());
+    }
+
+    assert_eq!(counts, vec![4, 3, 2, 1, 2]);
+}
+
+#[test]
+fn for_each_join_streams_each_match_once() {
+    let edges: Relation<(u32, u32)> = vec![(1, 2), (2, 3), (3, 4)].into();
+
+    let mut iteration = Iteration::new();
+    // reach(B, A) means that B is reachable from A.
+    let reach = iteration.variable::<(u32, u32)>();
+    reach.extend(edges.iter().map(|&(a, b)| (b, a)));
+
+    let mut streamed = Vec::new();
+    while iteration.changed() {
+        reach.from_join(&reach, &edges, |_, &a, &c| (c, a));
+        reach.for_each_join(&edges, |&b, &a, &c| streamed.push((a, b, c)));
+    }
+
+    streamed.sort();
+    assert_eq!(streamed, vec![(1, 2, 3), (1, 3, 4), (2, 3, 4)]);
+    assert_eq!(reach.complete().len(), 6);
+}
+
+#[test]
+fn complete_shrunk_releases_spare_capacity() {
+    let mut iteration = Iteration::new();
+    let numbers = iteration.variable::<u32>();
+    numbers.extend((0..1000).map(|x| x * 2));
+    let mut round = 0;
+    while iteration.changed() {
+        round += 1;
+        if round < 5 {
+            // Interleaved with the earlier tuples, so merges must copy.
+            numbers.extend((0..1000).map(|x| x * 2 + 1));
+            numbers.extend((1000..1100).map(|x| x * round));
+        }
+    }
+
+    let relation = numbers.complete_shrunk();
+    assert!(relation.is_sorted_and_distinct());
+    assert_eq!(relation.capacity(), relation.len());
+
+    let mut manual = Relation::<u32>::with_capacity(100);
+    manual.shrink_to_fit();
+    assert_eq!(manual.capacity(), 0);
+}
+
+#[test]
+fn from_join_adv_on_composite_keys() {
+    // ((function, block), statement) and ((function, block), successor)
+    let statements: Relation<((u32, u32), u32)> =
+        vec![((1, 0), 10), ((1, 1), 11), ((2, 0), 20)].into();
+    let successors: Relation<((u32, u32), u32)> =
+        vec![((1, 0), 1), ((2, 0), 3), ((2, 1), 0)].into();
+
+    let mut iteration = Iteration::new();
+    let statements_var = iteration.variable::<((u32, u32), u32)>();
+    let flows = iteration.variable::<(u32, u32, u32)>();
+    statements_var.insert(statements);
+    while iteration.changed() {
+        flows.from_join_adv(
+            &statements_var,
+            &successors,
+            |(fb, _)| fb,
+            |(fb, _)| fb,
+            |&(f, _), &(_, s), &(_, next)| (f, s, next),
+        );
+    }
+
+    assert_eq!(flows.complete().elements, vec![(1, 10, 1), (2, 20, 3)]);
+}
+
+#[test]
+fn set_distinct_switches_between_rounds() {
+    let mut iteration = Iteration::new();
+    let numbers = iteration.variable::<u32>();
+
+    numbers.extend(vec![1, 2]);
+    assert!(iteration.changed());
+    assert!(!iteration.changed());
+
+    // While indistinct, tuples already held become recent again.
+    numbers.set_distinct(false);
+    numbers.extend(vec![1]);
+    assert!(iteration.changed());
+    assert_eq!(numbers.recent_len(), 1);
+    assert!(!iteration.changed());
+
+    numbers.set_distinct(true);
+    numbers.extend(vec![2]);
+    assert!(!iteration.changed());
+    assert_eq!(numbers.recent_len(), 0);
+}
+
+#[test]
+fn flat_map_expands_filters_and_maps() {
+    let numbers: Relation<u32> = (1..=4).collect();
+
+    let filtered = numbers.flat_map(|&x| if x % 2 == 0 { Some(x) } else { None });
+    assert_eq!(filtered.elements, vec![2, 4]);
+
+    let mapped = numbers.flat_map(|&x| Some(5 - x));
+    assert_eq!(mapped.elements, vec![1, 2, 3, 4]);
+
+    // Overlapping expansions are deduplicated.
+    let expanded = numbers.flat_map(|&x| (x..x + 3).map(|y| y % 4));
+    assert_eq!(expanded.elements, vec![0, 1, 2, 3]);
+    assert!(numbers.flat_map(|_| Vec::<u32>::new()).is_empty());
+}
+
+#[test]
+fn from_map_lookup_handles_hits_and_misses() {
+    // (node, color)
+    let colors: Relation<(u32, u32)> = vec![(1, 7), (3, 9), (3, 8)].into();
+
+    let mut iteration = Iteration::new();
+    let edges = iteration.variable::<(u32, u32)>();
+    let colored = iteration.variable::<(u32, u32, u32)>();
+    edges.extend(vec![(1, 2), (2, 3), (3, 1)]);
+
+    while iteration.changed() {
+        colored.from_map_lookup(
+            &edges,
+            &colors,
+            |(a, _)| a,
+            |&(a, b), color| (a, b, *color.unwrap_or(&0)),
+        );
+    }
+
+    assert_eq!(
+        colored.complete().elements,
+        vec![(1, 2, 7), (2, 3, 0), (3, 1, 8)]
+    );
+}
+
+proptest! {
+    #[test]
+    fn merge_many_matches_pairwise_merge(shards in prop::collection::vec(inputs(), 0..6)) {
+        let relations: Vec<Relation<(u32, u32)>> =
+            shards.into_iter().map(Relation::from_vec).collect();
+        let empty = Relation::from_vec(Vec::new());
+        let pairwise = relations.iter().cloned().fold(empty, Relation::merge);
+        let merged = Relation::merge_many(relations);
+        assert_eq!(merged.elements, pairwise.elements);
+    }
+}
@@ -0,0 +1,66 @@
+//! Helpers for testing code that produces relations.
+
+use alloc::vec::Vec;
+use core::cmp::Ordering;
+use core::fmt::Debug;
+
+use super::Relation;
+
+/// Asserts that a relation is equal to a list of expected tuples.
+///
+/// The expected tuples are sorted and deduplicated before comparing, so
+/// they may be given in any order. On failure, the panic message lists
+/// the tuples that are missing from the relation and those that were
+/// not expected.
+///
+/// # Examples
+///
+/// ```
+/// use datafrog::{assert_relation_eq, Relation};
+///
+/// let relation: Relation<(u32, u32)> = vec![(2, 3), (1, 2)].into();
+/// assert_relation_eq!(relation, [(1, 2), (2, 3), (1, 2)]);
+/// ```
+#[macro_export]
+macro_rules! assert_relation_eq {
+    ($relation:expr, $expected:expr $(,)?) => {
+        $crate::testing::assert_relation_eq(&$relation, $expected)
+    };
+}
+
+/// Implements `assert_relation_eq!`.
+#[track_caller]
+pub fn assert_relation_eq<Tuple: Ord + Debug>(
+    relation: &Relation<Tuple>,
+    expected: impl IntoIterator<Item = Tuple>,
+) {
+    let expected = Relation::from_iter(expected);
+
+    let mut missing = Vec::new();
+    let mut unexpected = Vec::new();
+
+    let mut actual = relation.iter().peekable();
+    let mut expected = expected.iter().peekable();
+    loop {
+        match (actual.peek(), expected.peek()) {
+            (Some(a), Some(e)) => match a.cmp(e) {
+                Ordering::Less => unexpected.push(actual.next().unwrap()),
+                Ordering::Equal => {
+                    actual.next();
+                    expected.next();
+                }
+                Ordering::Greater => missing.push(expected.next().unwrap()),
+            },
+            (Some(_), None) => unexpected.push(actual.next().unwrap()),
+            (None, Some(_)) => missing.push(expected.next().unwrap()),
+            (None, None) => break,
+        }
+    }
+
+    if !missing.is_empty() || !unexpected.is_empty() {
+        panic!(
+            "relations are not equal\n   missing: {:?}\nunexpected: {:?}",
+            missing, unexpected,
+        );
+    }
+}
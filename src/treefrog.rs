@@ -1,5 +1,7 @@
 //! Join functionality.
 
+use alloc::vec::Vec;
+
 use super::Relation;
 
 /// Performs treefrog leapjoin using a list of leapers.
@@ -13,8 +15,8 @@ pub fn leapjoin<'leap, Tuple: Ord, Val: Ord + 'leap, Result: Ord>(
 
     for tuple in source {
         // Determine which leaper would propose the fewest values.
-        let mut min_index = usize::max_value();
-        let mut min_count = usize::max_value();
+        let mut min_index = usize::MAX;
+        let mut min_count = usize::MAX;
         leapers.for_each_count(tuple, |index, count| {
             if min_count > count {
                 min_count = count;
@@ -23,7 +25,7 @@ pub fn leapjoin<'leap, Tuple: Ord, Val: Ord + 'leap, Result: Ord>(
         });
 
         // We had best have at least one relation restricting values.
-        assert!(min_count < usize::max_value());
+        assert!(min_count < usize::MAX);
 
         // If there are values to propose:
         if min_count > 0 {
@@ -119,6 +121,7 @@ pub trait Leaper<'leap, Tuple, Val> {
 pub(crate) mod filters {
     use super::Leaper;
     use super::Leapers;
+    use alloc::vec::Vec;
 
     /// A treefrog leaper that tests each of the tuples from the main
     /// input (the "prefix"). Use like `PrefixFilter::from(|tuple|
@@ -127,18 +130,18 @@ pub(crate) mod filters {
     /// isolation in which case it just acts like a filter on the
     /// input (the "proposed value" will be `()` type).
     pub struct PrefixFilter<Tuple, Func: Fn(&Tuple) -> bool> {
-        phantom: ::std::marker::PhantomData<Tuple>,
+        phantom: ::core::marker::PhantomData<Tuple>,
         predicate: Func,
     }
 
-    impl<'leap, Tuple, Func> PrefixFilter<Tuple, Func>
+    impl<Tuple, Func> PrefixFilter<Tuple, Func>
     where
         Func: Fn(&Tuple) -> bool,
     {
         /// Creates a new filter based on the prefix
         pub fn from(predicate: Func) -> Self {
             PrefixFilter {
-                phantom: ::std::marker::PhantomData,
+                phantom: ::core::marker::PhantomData,
                 predicate,
             }
         }
@@ -151,7 +154,7 @@ pub(crate) mod filters {
         /// Estimates the number of proposed values.
         fn count(&mut self, prefix: &Tuple) -> usize {
             if (self.predicate)(prefix) {
-                usize::max_value()
+                usize::MAX
             } else {
                 0
             }
@@ -199,18 +202,18 @@ pub(crate) mod filters {
     /// This leaper cannot be used in isolation, it must be combined
     /// with other leapers.
     pub struct ValueFilter<Tuple, Val, Func: Fn(&Tuple, &Val) -> bool> {
-        phantom: ::std::marker::PhantomData<(Tuple, Val)>,
+        phantom: ::core::marker::PhantomData<(Tuple, Val)>,
         predicate: Func,
     }
 
-    impl<'leap, Tuple, Val, Func> ValueFilter<Tuple, Val, Func>
+    impl<Tuple, Val, Func> ValueFilter<Tuple, Val, Func>
     where
         Func: Fn(&Tuple, &Val) -> bool,
     {
         /// Creates a new filter based on the prefix
         pub fn from(predicate: Func) -> Self {
             ValueFilter {
-                phantom: ::std::marker::PhantomData,
+                phantom: ::core::marker::PhantomData,
                 predicate,
             }
         }
@@ -222,7 +225,7 @@ pub(crate) mod filters {
     {
         /// Estimates the number of proposed values.
         fn count(&mut self, _prefix: &Tuple) -> usize {
-            usize::max_value()
+            usize::MAX
         }
         /// Populates `values` with proposed values.
         fn propose(&mut self, _prefix: &Tuple, _values: &mut Vec<&'leap Val>) {
@@ -233,7 +236,6 @@ pub(crate) mod filters {
             values.retain(|val| (self.predicate)(prefix, val));
         }
     }
-
 }
 
 /// Extension method for relations.
@@ -318,8 +320,17 @@ impl<Key: Ord, Val: Ord> RelationLeaper<Key, Val> for Relation<(Key, Val)> {
 pub(crate) mod extend_with {
     use super::{binary_search, Leaper, Leapers, Relation};
     use crate::join::gallop;
+    use alloc::vec::Vec;
 
     /// Wraps a Relation<Tuple> as a leaper.
+    ///
+    /// For each source tuple, `key_func` selects a key, and the leaper
+    /// gallops to the range of tuples in the relation with that key: its
+    /// count is the size of that range, it proposes the values in the
+    /// range, and it restricts the values proposed by other leapers to
+    /// those in the range. Unlike `PrefixFilter`, which only accepts or
+    /// rejects source tuples, this leaper extends them with new values,
+    /// as in `r(a, b), r(b, c)`.
     pub struct ExtendWith<'leap, Key, Val, Tuple, Func>
     where
         Key: Ord + 'leap,
@@ -331,7 +342,7 @@ pub(crate) mod extend_with {
         start: usize,
         end: usize,
         key_func: Func,
-        phantom: ::std::marker::PhantomData<Tuple>,
+        phantom: ::core::marker::PhantomData<Tuple>,
     }
 
     impl<'leap, Key, Val, Tuple, Func> ExtendWith<'leap, Key, Val, Tuple, Func>
@@ -348,7 +359,7 @@ pub(crate) mod extend_with {
                 start: 0,
                 end: 0,
                 key_func,
-                phantom: ::std::marker::PhantomData,
+                phantom: ::core::marker::PhantomData,
             }
         }
     }
@@ -363,21 +374,21 @@ pub(crate) mod extend_with {
     {
         fn count(&mut self, prefix: &Tuple) -> usize {
             let key = (self.key_func)(prefix);
-            self.start = binary_search(&self.relation[..], |x| &x.0 < &key);
+            self.start = binary_search(&self.relation[..], |x| x.0 < key);
             let slice1 = &self.relation[self.start..];
-            let slice2 = gallop(slice1, |x| &x.0 <= &key);
+            let slice2 = gallop(slice1, |x| x.0 <= key);
             self.end = self.relation.len() - slice2.len();
             slice1.len() - slice2.len()
         }
         fn propose(&mut self, _prefix: &Tuple, values: &mut Vec<&'leap Val>) {
             let slice = &self.relation[self.start..self.end];
-            values.extend(slice.iter().map(|&(_, ref val)| val));
+            values.extend(slice.iter().map(|(_, val)| val));
         }
         fn intersect(&mut self, _prefix: &Tuple, values: &mut Vec<&'leap Val>) {
             let mut slice = &self.relation[self.start..self.end];
             values.retain(|v| {
                 slice = gallop(slice, |kv| &kv.1 < v);
-                slice.get(0).map(|kv| &kv.1) == Some(v)
+                slice.first().map(|kv| &kv.1) == Some(v)
             });
         }
     }
@@ -405,9 +416,99 @@ pub(crate) mod extend_with {
     }
 }
 
+pub(crate) mod extend_with_index {
+    use super::{Leaper, Leapers};
+    use crate::index::RelationIndex;
+    use crate::join::gallop;
+    use alloc::vec::Vec;
+
+    /// Wraps a RelationIndex as a leaper.
+    ///
+    /// This works like `ExtendWith`, but looks up the values for each key
+    /// in the index instead of galloping through a relation.
+    pub struct ExtendWithIndex<'leap, Key, Val, Tuple, Func>
+    where
+        Key: Ord + 'leap,
+        Val: Ord + 'leap,
+        Tuple: Ord,
+        Func: Fn(&Tuple) -> Key,
+    {
+        index: &'leap RelationIndex<Key, Val>,
+        values: &'leap [Val],
+        key_func: Func,
+        phantom: ::core::marker::PhantomData<Tuple>,
+    }
+
+    impl<'leap, Key, Val, Tuple, Func> ExtendWithIndex<'leap, Key, Val, Tuple, Func>
+    where
+        Key: Ord + 'leap,
+        Val: Ord + 'leap,
+        Tuple: Ord,
+        Func: Fn(&Tuple) -> Key,
+    {
+        /// Constructs a ExtendWithIndex from an index and key function.
+        pub fn from(index: &'leap RelationIndex<Key, Val>, key_func: Func) -> Self {
+            ExtendWithIndex {
+                index,
+                values: &[],
+                key_func,
+                phantom: ::core::marker::PhantomData,
+            }
+        }
+    }
+
+    impl<'leap, Key, Val, Tuple, Func> Leaper<'leap, Tuple, Val>
+        for ExtendWithIndex<'leap, Key, Val, Tuple, Func>
+    where
+        Key: Ord + 'leap,
+        Val: Ord + 'leap,
+        Tuple: Ord,
+        Func: Fn(&Tuple) -> Key,
+    {
+        fn count(&mut self, prefix: &Tuple) -> usize {
+            let key = (self.key_func)(prefix);
+            self.values = self.index.get(&key);
+            self.values.len()
+        }
+        fn propose(&mut self, _prefix: &Tuple, values: &mut Vec<&'leap Val>) {
+            values.extend(self.values.iter());
+        }
+        fn intersect(&mut self, _prefix: &Tuple, values: &mut Vec<&'leap Val>) {
+            let mut slice = self.values;
+            values.retain(|v| {
+                slice = gallop(slice, |x| x < v);
+                slice.first() == Some(v)
+            });
+        }
+    }
+
+    impl<'leap, Key, Val, Tuple, Func> Leapers<'leap, Tuple, Val>
+        for ExtendWithIndex<'leap, Key, Val, Tuple, Func>
+    where
+        Key: Ord + 'leap,
+        Val: Ord + 'leap,
+        Tuple: Ord,
+        Func: Fn(&Tuple) -> Key,
+    {
+        fn for_each_count(&mut self, tuple: &Tuple, mut op: impl FnMut(usize, usize)) {
+            op(0, self.count(tuple))
+        }
+
+        fn propose(&mut self, tuple: &Tuple, min_index: usize, values: &mut Vec<&'leap Val>) {
+            assert_eq!(min_index, 0);
+            Leaper::propose(self, tuple, values);
+        }
+
+        fn intersect(&mut self, _: &Tuple, min_index: usize, _: &mut Vec<&'leap Val>) {
+            assert_eq!(min_index, 0);
+        }
+    }
+}
+
 pub(crate) mod extend_anti {
     use super::{binary_search, Leaper, Relation};
     use crate::join::gallop;
+    use alloc::vec::Vec;
 
     /// Wraps a Relation<Tuple> as a leaper.
     pub struct ExtendAnti<'leap, Key, Val, Tuple, Func>
@@ -419,7 +520,7 @@ pub(crate) mod extend_anti {
     {
         relation: &'leap Relation<(Key, Val)>,
         key_func: Func,
-        phantom: ::std::marker::PhantomData<Tuple>,
+        phantom: ::core::marker::PhantomData<Tuple>,
     }
 
     impl<'leap, Key, Val, Tuple, Func> ExtendAnti<'leap, Key, Val, Tuple, Func>
@@ -434,7 +535,7 @@ pub(crate) mod extend_anti {
             ExtendAnti {
                 relation,
                 key_func,
-                phantom: ::std::marker::PhantomData,
+                phantom: ::core::marker::PhantomData,
             }
         }
     }
@@ -448,21 +549,21 @@ pub(crate) mod extend_anti {
         Func: Fn(&Tuple) -> Key,
     {
         fn count(&mut self, _prefix: &Tuple) -> usize {
-            usize::max_value()
+            usize::MAX
         }
         fn propose(&mut self, _prefix: &Tuple, _values: &mut Vec<&'leap Val>) {
             panic!("ExtendAnti::propose(): variable apparently unbound.");
         }
         fn intersect(&mut self, prefix: &Tuple, values: &mut Vec<&'leap Val>) {
             let key = (self.key_func)(prefix);
-            let start = binary_search(&self.relation[..], |x| &x.0 < &key);
+            let start = binary_search(&self.relation[..], |x| x.0 < key);
             let slice1 = &self.relation[start..];
-            let slice2 = gallop(slice1, |x| &x.0 <= &key);
+            let slice2 = gallop(slice1, |x| x.0 <= key);
             let mut slice = &slice1[..(slice1.len() - slice2.len())];
             if !slice.is_empty() {
                 values.retain(|v| {
                     slice = gallop(slice, |kv| &kv.1 < v);
-                    slice.get(0).map(|kv| &kv.1) != Some(v)
+                    slice.first().map(|kv| &kv.1) != Some(v)
                 });
             }
         }
@@ -472,6 +573,7 @@ pub(crate) mod extend_anti {
 pub(crate) mod filter_with {
 
     use super::{Leaper, Leapers, Relation};
+    use alloc::vec::Vec;
 
     /// Wraps a Relation<Tuple> as a leaper.
     pub struct FilterWith<'leap, Key, Val, Tuple, Func>
@@ -483,7 +585,7 @@ pub(crate) mod filter_with {
     {
         relation: &'leap Relation<(Key, Val)>,
         key_func: Func,
-        phantom: ::std::marker::PhantomData<Tuple>,
+        phantom: ::core::marker::PhantomData<Tuple>,
     }
 
     impl<'leap, Key, Val, Tuple, Func> FilterWith<'leap, Key, Val, Tuple, Func>
@@ -498,7 +600,7 @@ pub(crate) mod filter_with {
             FilterWith {
                 relation,
                 key_func,
-                phantom: ::std::marker::PhantomData,
+                phantom: ::core::marker::PhantomData,
             }
         }
     }
@@ -514,7 +616,7 @@ pub(crate) mod filter_with {
         fn count(&mut self, prefix: &Tuple) -> usize {
             let key_val = (self.key_func)(prefix);
             if self.relation.binary_search(&key_val).is_ok() {
-                usize::max_value()
+                usize::MAX
             } else {
                 0
             }
@@ -558,6 +660,7 @@ pub(crate) mod filter_with {
 pub(crate) mod filter_anti {
 
     use super::{Leaper, Leapers, Relation};
+    use alloc::vec::Vec;
 
     /// Wraps a Relation<Tuple> as a leaper.
     pub struct FilterAnti<'leap, Key, Val, Tuple, Func>
@@ -569,7 +672,7 @@ pub(crate) mod filter_anti {
     {
         relation: &'leap Relation<(Key, Val)>,
         key_func: Func,
-        phantom: ::std::marker::PhantomData<Tuple>,
+        phantom: ::core::marker::PhantomData<Tuple>,
     }
 
     impl<'leap, Key, Val, Tuple, Func> FilterAnti<'leap, Key, Val, Tuple, Func>
@@ -584,7 +687,7 @@ pub(crate) mod filter_anti {
             FilterAnti {
                 relation,
                 key_func,
-                phantom: ::std::marker::PhantomData,
+                phantom: ::core::marker::PhantomData,
             }
         }
     }
@@ -602,7 +705,7 @@ pub(crate) mod filter_anti {
             if self.relation.binary_search(&key_val).is_ok() {
                 0
             } else {
-                usize::max_value()
+                usize::MAX
             }
         }
         fn propose(&mut self, _prefix: &Tuple, _values: &mut Vec<&'leap Val2>) {